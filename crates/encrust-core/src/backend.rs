@@ -0,0 +1,530 @@
+//! Keystream backends used by [`Encrusted`](crate::Encrusted) to obfuscate data.
+//!
+//! A backend turns a key into a keystream that is combined with the underlying data using XOR. The
+//! default backend, [`SmallRngBackend`], is fast but not cryptographically secure and only uses a
+//! 64-bit seed. Stronger backends using 256-bit keys are available behind the `chacha20` and `aes`
+//! feature flags.
+//!
+//! Every backend has a version identifying its algorithm. Data obfuscated at compile time or
+//! persisted using an older version is deobfuscated using the algorithm of that version and
+//! obfuscated again using the current version, so changes to the algorithms do not break existing
+//! data.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(any(feature = "std", feature = "chacha20"))]
+use rand::RngCore;
+#[cfg(feature = "chacha20")]
+use rand::SeedableRng;
+use zeroize::Zeroize;
+
+/// Trait implemented by keystreams used to obfuscate and deobfuscate data, see
+/// [`Encrustable::toggle_encrust`](crate::Encrustable::toggle_encrust).
+///
+/// Data is obfuscated by combining it with the keystream using XOR, so generating the same
+/// keystream again and combining it with the obfuscated data deobfuscates it.
+pub trait Keystream {
+    /// Fills `dst` with the next `dst.len()` bytes of the keystream.
+    fn fill(&mut self, dst: &mut [u8]);
+
+    /// Combines `data` with the next `data.len()` bytes of the keystream using XOR.
+    ///
+    /// The default implementation fills a buffer of up to 64 bytes at a time using
+    /// [`Keystream::fill`], and zeroizes it afterwards.
+    fn xor_into(&mut self, data: &mut [u8]) {
+        let mut key = [0u8; 64];
+        for chunk in data.chunks_mut(64) {
+            let key = &mut key[..chunk.len()];
+            self.fill(key);
+
+            for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
+                *byte ^= byte_key;
+            }
+        }
+
+        key.zeroize();
+    }
+}
+
+/// Trait implemented by types used to generate the keystream for [`Encrusted`](crate::Encrusted).
+///
+/// Backends are never instantiated, they are only used as a type parameter to select how data is
+/// obfuscated.
+pub trait Backend {
+    /// The key (or seed) used to create a keystream. It is stored next to the obfuscated data and
+    /// zeroized when the data is dropped.
+    type Key: Zeroize;
+    /// The keystream generator created from a key.
+    type Keystream: Keystream;
+
+    /// The version of the algorithm used to generate keystreams. It must be incremented whenever
+    /// the keystream or the MAC keystream generated from a key changes, and stored together with
+    /// persisted data.
+    const VERSION: u8;
+
+    /// The number of bytes of random or derived material needed to create a key, see
+    /// [`Backend::key_from_material`].
+    const KEY_SIZE: usize;
+
+    /// Creates a new keystream from `key`. Calling this function twice with the same key must
+    /// produce identical keystreams.
+    fn keystream(key: &Self::Key) -> Self::Keystream;
+
+    /// Creates the keystream generated from `key` by version `version` of the algorithm, used to
+    /// deobfuscate data obfuscated by earlier versions. Returns `None` if `version` is not
+    /// supported.
+    ///
+    /// The default implementation only supports [`Backend::VERSION`]. Backends changing their
+    /// algorithm must keep generating the keystreams of earlier versions here.
+    fn versioned_keystream(key: &Self::Key, version: u8) -> Option<Self::Keystream> {
+        (version == Self::VERSION).then(|| Self::keystream(key))
+    }
+
+    /// Creates the keystream used to key the MAC of authenticated data when the `integrity` feature
+    /// is enabled. Calling this function twice with the same key must produce identical keystreams.
+    ///
+    /// The MAC keystream must be independent of the keystream returned by [`Backend::keystream`],
+    /// otherwise anyone knowing part of the obfuscated data can recover the MAC key and forge
+    /// tags.
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream;
+
+    /// Creates a key from `material`, which is exactly [`Backend::KEY_SIZE`] bytes of random or
+    /// derived key material. Implementations may panic if `material` has a different length.
+    fn key_from_material(material: &[u8]) -> Self::Key;
+}
+
+/// Trait implemented by backends whose keystream can be positioned at any block of 64 bytes.
+///
+/// Seekable backends allow parts of large [`EncrustedBytes`](crate::EncrustedBytes) buffers to be
+/// deobfuscated without generating the keystream for the data before it, see
+/// [`Encrusted::decrust_range`](crate::Encrusted::decrust_range).
+pub trait SeekableBackend: Backend {
+    /// Positions `keystream` at the start of the 64-byte block with index `block`, so the next
+    /// bytes generated are bytes `64 * block..` of the keystream.
+    fn seek(keystream: &mut Self::Keystream, block: u64);
+}
+
+/// Trait implemented by backends whose keys can be stored in sealed blobs, see
+/// [`Encrusted::to_sealed_bytes`](crate::Encrusted::to_sealed_bytes).
+pub trait SealableBackend: Backend {
+    /// Identifier of the backend stored in sealed blobs. Blobs are only opened by the backend that
+    /// sealed them.
+    const ALGORITHM: u16;
+
+    /// Returns the bytes of `key` as stored in sealed blobs.
+    fn key_to_bytes(key: &Self::Key) -> Vec<u8>;
+
+    /// Creates a key from bytes returned by [`SealableBackend::key_to_bytes`]. Returns `None` if
+    /// the bytes are not a valid key.
+    fn key_from_bytes(bytes: &[u8]) -> Option<Self::Key>;
+}
+
+/// Returns `true` if the backend `B` can deobfuscate data obfuscated using `key` and version
+/// `version` of its algorithm.
+pub(crate) fn supports_version<B>(key: &B::Key, version: u8) -> bool
+where
+    B: Backend,
+{
+    version == B::VERSION || B::versioned_keystream(key, version).is_some()
+}
+
+/// Generates a random key for the backend `B` using the thread-local random number generator.
+#[cfg(feature = "std")]
+pub(crate) fn random_key<B>() -> B::Key
+where
+    B: Backend,
+{
+    let mut material = vec![0; B::KEY_SIZE];
+    rand::rng().fill_bytes(&mut material);
+
+    let key = B::key_from_material(&material);
+    material.zeroize();
+
+    key
+}
+
+/// The default backend, using [`SmallRngKeystream`] seeded with a `u64`.
+///
+/// This backend is fast, but neither the keystream nor the key size is suitable if stronger
+/// obfuscation is required.
+pub struct SmallRngBackend;
+
+impl Backend for SmallRngBackend {
+    type Key = u64;
+    type Keystream = SmallRngKeystream;
+
+    const VERSION: u8 = 1;
+    const KEY_SIZE: usize = 8;
+
+    fn keystream(key: &Self::Key) -> Self::Keystream {
+        SmallRngKeystream::seed_from_u64(*key)
+    }
+
+    /// The MAC keystream is seeded with the key combined with a constant. Recovering the key from
+    /// the data keystream is feasible for this backend, so this only prevents the MAC key from
+    /// being read directly from known data.
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+        // "encrust:" as a little-endian number.
+        const MAC_SEED: u64 = 0x3a74_7375_7263_6e65;
+
+        SmallRngKeystream::seed_from_u64(*key ^ MAC_SEED)
+    }
+
+    fn key_from_material(material: &[u8]) -> Self::Key {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(material);
+
+        let key = u64::from_le_bytes(bytes);
+        bytes.zeroize();
+
+        key
+    }
+}
+
+impl SealableBackend for SmallRngBackend {
+    const ALGORITHM: u16 = 1;
+
+    fn key_to_bytes(key: &Self::Key) -> Vec<u8> {
+        key.to_le_bytes().to_vec()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> Option<Self::Key> {
+        Some(Self::Key::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// Keystream generated by [`SmallRngBackend`], using the `Xoshiro256++` generator seeded using
+/// `SplitMix64`.
+///
+/// This is the generator used by `rand`'s `SmallRng` on 64-bit platforms. It is part of encrust so
+/// that the keystream does not change with the version of `rand` or the pointer width of the
+/// platform, as `SmallRng` uses a different generator on 32-bit platforms.
+pub struct SmallRngKeystream {
+    state: [u64; 4],
+}
+
+impl SmallRngKeystream {
+    /// Creates a keystream from a 64-bit seed, expanding it to the state of the generator using
+    /// `SplitMix64`.
+    fn seed_from_u64(mut seed: u64) -> Self {
+        const PHI: u64 = 0x9e37_79b9_7f4a_7c15;
+
+        let state = core::array::from_fn(|_| {
+            seed = seed.wrapping_add(PHI);
+
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        });
+
+        Self { state }
+    }
+}
+
+impl SmallRngKeystream {
+    fn next_u32(&mut self) -> u32 {
+        // The upper bits have the best statistical quality.
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+
+        let result = s0.wrapping_add(*s3).rotate_left(23).wrapping_add(*s0);
+        let t = *s1 << 17;
+
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+
+        result
+    }
+}
+
+impl Keystream for SmallRngKeystream {
+    fn fill(&mut self, dst: &mut [u8]) {
+        let mut chunks = dst.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+
+        let rest = chunks.into_remainder();
+        if rest.len() > 4 {
+            rest.copy_from_slice(&self.next_u64().to_le_bytes()[..rest.len()]);
+        } else if !rest.is_empty() {
+            rest.copy_from_slice(&self.next_u32().to_le_bytes()[..rest.len()]);
+        }
+    }
+}
+
+impl Drop for SmallRngKeystream {
+    fn drop(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+/// Backend using the `ChaCha20` stream cipher with a 256-bit key.
+#[cfg(feature = "chacha20")]
+pub struct ChaCha20Backend;
+
+#[cfg(feature = "chacha20")]
+impl Backend for ChaCha20Backend {
+    type Key = [u8; 32];
+    type Keystream = ChaCha20Keystream;
+
+    const VERSION: u8 = 1;
+    const KEY_SIZE: usize = 32;
+
+    fn keystream(key: &Self::Key) -> Self::Keystream {
+        ChaCha20Keystream(rand_chacha::ChaCha20Rng::from_seed(*key))
+    }
+
+    /// The MAC keystream uses stream 1 of the key, while data is obfuscated using stream 0.
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+        let mut rng = rand_chacha::ChaCha20Rng::from_seed(*key);
+        rng.set_stream(1);
+
+        ChaCha20Keystream(rng)
+    }
+
+    fn key_from_material(material: &[u8]) -> Self::Key {
+        let mut key = [0; 32];
+        key.copy_from_slice(material);
+
+        key
+    }
+}
+
+/// Keystream generated by [`ChaCha20Backend`].
+#[cfg(feature = "chacha20")]
+pub struct ChaCha20Keystream(rand_chacha::ChaCha20Rng);
+
+#[cfg(feature = "chacha20")]
+impl Keystream for ChaCha20Keystream {
+    fn fill(&mut self, dst: &mut [u8]) {
+        self.0.fill_bytes(dst);
+    }
+}
+
+#[cfg(feature = "chacha20")]
+impl SealableBackend for ChaCha20Backend {
+    const ALGORITHM: u16 = 2;
+
+    fn key_to_bytes(key: &Self::Key) -> Vec<u8> {
+        key.to_vec()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> Option<Self::Key> {
+        bytes.try_into().ok()
+    }
+}
+
+#[cfg(feature = "chacha20")]
+impl SeekableBackend for ChaCha20Backend {
+    fn seek(keystream: &mut Self::Keystream, block: u64) {
+        // The position is given in 32-bit words, 16 words per 64-byte block.
+        keystream.0.set_word_pos(u128::from(block) * 16);
+    }
+}
+
+/// Backend using AES-256 in counter mode with a 256-bit key.
+///
+/// The counter of the data keystream always starts at zero, so the keystream is fully determined by
+/// the key. The MAC keystream starts with the highest bit of the counter set.
+#[cfg(feature = "aes")]
+pub struct AesCtrBackend;
+
+#[cfg(feature = "aes")]
+impl Backend for AesCtrBackend {
+    type Key = [u8; 32];
+    type Keystream = AesCtrKeystream;
+
+    const VERSION: u8 = 1;
+    const KEY_SIZE: usize = 32;
+
+    fn keystream(key: &Self::Key) -> Self::Keystream {
+        use ctr::cipher::KeyIvInit;
+
+        AesCtrKeystream(ctr::Ctr128BE::new(key.into(), &[0; 16].into()))
+    }
+
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+        use ctr::cipher::KeyIvInit;
+
+        // The data keystream would need 2^127 blocks to reach this counter.
+        let mut counter = [0; 16];
+        counter[0] = 0x80;
+
+        AesCtrKeystream(ctr::Ctr128BE::new(key.into(), &counter.into()))
+    }
+
+    fn key_from_material(material: &[u8]) -> Self::Key {
+        let mut key = [0; 32];
+        key.copy_from_slice(material);
+
+        key
+    }
+}
+
+#[cfg(feature = "aes")]
+impl SealableBackend for AesCtrBackend {
+    const ALGORITHM: u16 = 3;
+
+    fn key_to_bytes(key: &Self::Key) -> Vec<u8> {
+        key.to_vec()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> Option<Self::Key> {
+        bytes.try_into().ok()
+    }
+}
+
+#[cfg(feature = "aes")]
+impl SeekableBackend for AesCtrBackend {
+    fn seek(keystream: &mut Self::Keystream, block: u64) {
+        use ctr::cipher::StreamCipherSeek;
+
+        keystream.0.seek(u128::from(block) * 64);
+    }
+}
+
+/// Keystream generated by [`AesCtrBackend`].
+#[cfg(feature = "aes")]
+pub struct AesCtrKeystream(ctr::Ctr128BE<aes::Aes256>);
+
+#[cfg(feature = "aes")]
+impl Keystream for AesCtrKeystream {
+    fn fill(&mut self, dst: &mut [u8]) {
+        dst.fill(0);
+        self.xor_into(dst);
+    }
+
+    fn xor_into(&mut self, data: &mut [u8]) {
+        use ctr::cipher::StreamCipher;
+
+        self.0.apply_keystream(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn small_rng_keystream_matches_rand() {
+        use rand::{RngCore, SeedableRng, rngs::SmallRng};
+
+        for seed in [0, 0x2357_bd11, u64::MAX] {
+            let mut keystream = SmallRngBackend::keystream(&seed);
+            let mut small_rng = SmallRng::seed_from_u64(seed);
+
+            for len in [0, 1, 4, 5, 8, 13, 64, 100] {
+                let mut expected = vec![0; len];
+                small_rng.fill_bytes(&mut expected);
+
+                let mut actual = vec![0; len];
+                keystream.fill(&mut actual);
+
+                assert_eq!(expected, actual);
+            }
+
+            assert_eq!(small_rng.next_u32(), keystream.next_u32());
+            assert_eq!(small_rng.next_u64(), keystream.next_u64());
+        }
+    }
+
+    #[test]
+    fn small_rng_keystream_is_stable() {
+        // The first bytes generated from the seed 0x2357_bd11, which must never change as data
+        // obfuscated using version 1 depends on them.
+        let mut keystream = SmallRngBackend::keystream(&0x2357_bd11);
+        let mut bytes = [0; 16];
+        keystream.fill(&mut bytes);
+
+        assert_eq!(
+            [
+                0xf6, 0xbb, 0xe5, 0xdd, 0x8f, 0x1b, 0x17, 0xef, 0xad, 0x0a, 0x8c, 0x5f, 0x43, 0x3e,
+                0xef, 0xa4
+            ],
+            bytes
+        );
+    }
+
+    #[test]
+    fn xor_into() {
+        let mut data = [0x55; 100];
+        SmallRngBackend::keystream(&0x2357_bd11).xor_into(&mut data);
+
+        let mut expected = [0; 100];
+        SmallRngBackend::keystream(&0x2357_bd11).fill(&mut expected);
+        for byte in &mut expected {
+            *byte ^= 0x55;
+        }
+
+        assert_eq!(expected, data);
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_ctr_xor_into() {
+        let mut data = [0x55; 100];
+        AesCtrBackend::keystream(&[0x42; 32]).xor_into(&mut data);
+
+        let mut expected = [0; 100];
+        AesCtrBackend::keystream(&[0x42; 32]).fill(&mut expected);
+        for byte in &mut expected {
+            *byte ^= 0x55;
+        }
+
+        assert_eq!(expected, data);
+    }
+
+    #[test]
+    fn versioned_keystream() {
+        let mut current = SmallRngBackend::keystream(&0x2357_bd11);
+        let mut versioned =
+            SmallRngBackend::versioned_keystream(&0x2357_bd11, SmallRngBackend::VERSION).unwrap();
+        assert_eq!(current.next_u64(), versioned.next_u64());
+
+        assert!(SmallRngBackend::versioned_keystream(&0x2357_bd11, 0).is_none());
+        assert!(supports_version::<SmallRngBackend>(&0x2357_bd11, 1));
+        assert!(!supports_version::<SmallRngBackend>(&0x2357_bd11, 2));
+    }
+
+    /// Asserts that the MAC keystream of `key` does not overlap with the start of its data
+    /// keystream.
+    fn assert_independent_mac_keystream<B>(key: &B::Key)
+    where
+        B: Backend,
+    {
+        let mut data = [0; 256];
+        B::keystream(key).fill(&mut data);
+
+        let mut mac = [0; 64];
+        B::mac_keystream(key).fill(&mut mac);
+        assert!(data.windows(mac.len()).all(|window| window != mac));
+    }
+
+    #[test]
+    fn small_rng_mac_keystream() {
+        assert_independent_mac_keystream::<SmallRngBackend>(&0x2357_bd11);
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn chacha20_mac_keystream() {
+        assert_independent_mac_keystream::<ChaCha20Backend>(&[0x42; 32]);
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_ctr_mac_keystream() {
+        assert_independent_mac_keystream::<AesCtrBackend>(&[0x42; 32]);
+    }
+}
@@ -0,0 +1,355 @@
+//! Building obfuscated byte buffers and strings incrementally.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{fmt, io};
+
+use zeroize::Zeroizing;
+
+#[cfg(feature = "integrity")]
+use crate::integrity::Integrity;
+use crate::{Backend, Bytes, Encrusted, Keystream, SmallRngBackend, growth::reserve_zeroizing};
+
+/// Number of bytes obfuscated using each block of keystream by [`Bytes`].
+const BYTES_BLOCK_SIZE: usize = 64;
+/// Number of bytes obfuscated using each block of keystream by `String`.
+const STRING_BLOCK_SIZE: usize = 16;
+
+/// Obfuscates `bytes` and appends them to `data`, which holds bytes obfuscated using the same
+/// keystream. `block` holds the current block of keystream.
+///
+/// The capacity of `data` is grown by moving the obfuscated bytes into a new buffer and zeroizing
+/// the old one.
+fn append<const BLOCK_SIZE: usize>(
+    data: &mut Vec<u8>,
+    keystream: &mut impl Keystream,
+    block: &mut [u8; BLOCK_SIZE],
+    bytes: &[u8],
+) {
+    reserve_zeroizing(data, bytes.len());
+
+    for byte in bytes {
+        let offset = data.len() % BLOCK_SIZE;
+        if offset == 0 {
+            keystream.fill(block);
+        }
+
+        data.push(byte ^ block[offset]);
+    }
+}
+
+/// Builder for [`EncrustedBytes`](crate::EncrustedBytes), obfuscating bytes as they are written.
+///
+/// Pushing to a deobfuscated `Vec<u8>` exposes all of its contents, and reallocations leave copies
+/// of the data in freed memory. `EncrustedBytesBuilder` only ever stores obfuscated bytes, and
+/// grows its buffer by copying the obfuscated bytes, so deobfuscated data is never handled. Bytes
+/// are added using `std::io::Write` or [`fmt::Write`], and [`finish`](Self::finish) returns the
+/// resulting [`Encrusted`] object.
+///
+/// To avoid regenerating the keystream for every write, the builder keeps the keystream and its
+/// current block in memory until it is finished. The keystream holds state derived from the key,
+/// so with backends such as [`Provided`](crate::Provided) and `KeyringBackend`, which derive their
+/// keys when needed instead of storing them, the derived key remains in memory for the lifetime of
+/// the builder. Finish builders as soon as all data has been written.
+///
+/// # Example
+/// ```
+/// use encrust_core::EncrustedBytesBuilder;
+///
+/// let mut builder = EncrustedBytesBuilder::new(0xc0ffee);
+/// builder.append(b"A secret");
+/// builder.append(b" key");
+///
+/// let mut key = builder.finish();
+/// assert_eq!(b"A secret key".to_vec(), *key.decrust());
+/// ```
+pub struct EncrustedBytesBuilder<B = SmallRngBackend>
+where
+    B: Backend,
+{
+    encrusted: Encrusted<Bytes, B>,
+    keystream: B::Keystream,
+    block: Zeroizing<[u8; BYTES_BLOCK_SIZE]>,
+}
+
+impl EncrustedBytesBuilder {
+    /// Creates an empty builder obfuscating data using the provided seed and the default
+    /// [`SmallRngBackend`].
+    pub fn new(seed: u64) -> Self {
+        Self::with_key(seed)
+    }
+}
+
+impl<B> EncrustedBytesBuilder<B>
+where
+    B: Backend,
+{
+    /// Creates an empty builder obfuscating data using the provided key and the backend `B`.
+    pub fn with_key(key: B::Key) -> Self {
+        let encrusted = Encrusted::with_key(Bytes::default(), key);
+
+        Self {
+            keystream: B::keystream(&encrusted.key),
+            encrusted,
+            block: Zeroizing::new([0; BYTES_BLOCK_SIZE]),
+        }
+    }
+
+    /// Works like [`EncrustedBytesBuilder::with_key`], but uses a random key generated using the
+    /// thread-local random number generator.
+    #[cfg(feature = "std")]
+    pub fn with_random_key() -> Self {
+        Self::with_key(crate::backend::random_key::<B>())
+    }
+
+    /// Obfuscates `bytes` and appends them to the buffer.
+    pub fn append(&mut self, bytes: &[u8]) {
+        append(
+            &mut self.encrusted.data,
+            &mut self.keystream,
+            &mut self.block,
+            bytes,
+        );
+
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        self.encrusted.hardened.update(&mut self.encrusted.data);
+    }
+
+    /// Returns the number of bytes written.
+    pub fn len(&self) -> usize {
+        self.encrusted.data.len()
+    }
+
+    /// Returns `true` if no bytes have been written.
+    pub fn is_empty(&self) -> bool {
+        self.encrusted.data.is_empty()
+    }
+
+    /// Returns the obfuscated bytes.
+    pub fn finish(self) -> Encrusted<Bytes, B> {
+        self.encrusted
+    }
+
+    /// Works like [`EncrustedBytesBuilder::finish`], but additionally stores a MAC of the
+    /// obfuscated data that is verified by [`Encrusted::try_decrust`].
+    #[cfg(feature = "integrity")]
+    pub fn finish_authenticated(self) -> Encrusted<Bytes, B> {
+        let mut encrusted = self.encrusted;
+        encrusted.integrity = Some(Integrity::new(&encrusted.data, &encrusted.key));
+
+        encrusted
+    }
+}
+
+#[cfg(feature = "std")]
+impl<B> io::Write for EncrustedBytesBuilder<B>
+where
+    B: Backend,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B> fmt::Write for EncrustedBytesBuilder<B>
+where
+    B: Backend,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+/// Builder for `Encrusted<String>`, obfuscating text as it is written using [`fmt::Write`]. See
+/// [`EncrustedBytesBuilder`].
+///
+/// Like [`EncrustedBytesBuilder`], the builder keeps the keystream in memory until it is finished,
+/// including state derived from keys that backends such as [`Provided`](crate::Provided) otherwise
+/// only derive when needed.
+///
+/// # Example
+/// ```
+/// use std::fmt::Write;
+///
+/// use encrust_core::EncrustedStringBuilder;
+///
+/// let mut builder = EncrustedStringBuilder::new(0xc0ffee);
+/// write!(builder, "user:{}", "password").unwrap();
+///
+/// let mut credentials = builder.finish();
+/// assert_eq!("user:password", credentials.decrust().as_str());
+/// ```
+pub struct EncrustedStringBuilder<B = SmallRngBackend>
+where
+    B: Backend,
+{
+    encrusted: Encrusted<String, B>,
+    keystream: B::Keystream,
+    block: Zeroizing<[u8; STRING_BLOCK_SIZE]>,
+}
+
+impl EncrustedStringBuilder {
+    /// Creates an empty builder obfuscating text using the provided seed and the default
+    /// [`SmallRngBackend`].
+    pub fn new(seed: u64) -> Self {
+        Self::with_key(seed)
+    }
+}
+
+impl<B> EncrustedStringBuilder<B>
+where
+    B: Backend,
+{
+    /// Creates an empty builder obfuscating text using the provided key and the backend `B`.
+    pub fn with_key(key: B::Key) -> Self {
+        let encrusted = Encrusted::with_key(String::new(), key);
+
+        Self {
+            keystream: B::keystream(&encrusted.key),
+            encrusted,
+            block: Zeroizing::new([0; STRING_BLOCK_SIZE]),
+        }
+    }
+
+    /// Works like [`EncrustedStringBuilder::with_key`], but uses a random key generated using the
+    /// thread-local random number generator.
+    #[cfg(feature = "std")]
+    pub fn with_random_key() -> Self {
+        Self::with_key(crate::backend::random_key::<B>())
+    }
+
+    /// Obfuscates `s` and appends it to the string.
+    pub fn append(&mut self, s: &str) {
+        // SAFETY:
+        // The string holds obfuscated bytes that are only accessed as a `String` after being
+        // deobfuscated, at which point they are the valid UTF-8 appended here.
+        let data = unsafe { self.encrusted.data.as_mut_vec() };
+        append(data, &mut self.keystream, &mut self.block, s.as_bytes());
+
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        self.encrusted.hardened.update(&mut self.encrusted.data);
+    }
+
+    /// Removes the text after the first `len` bytes, regenerating the keystream up to `len`.
+    ///
+    /// `len` must be at a character boundary of the text written, which is not checked as the text
+    /// is obfuscated.
+    #[cfg(all(feature = "prompt", unix))]
+    pub(crate) fn truncate(&mut self, len: usize) {
+        // SAFETY:
+        // The string holds obfuscated bytes, and `len` is at a character boundary of the text.
+        let data = unsafe { self.encrusted.data.as_mut_vec() };
+        data.truncate(len);
+
+        self.keystream = B::keystream(&self.encrusted.key);
+        for _ in 0..len.div_ceil(STRING_BLOCK_SIZE) {
+            self.keystream.fill(&mut *self.block);
+        }
+    }
+
+    /// Returns the length of the text written, in bytes.
+    pub fn len(&self) -> usize {
+        self.encrusted.data.len()
+    }
+
+    /// Returns `true` if no text has been written.
+    pub fn is_empty(&self) -> bool {
+        self.encrusted.data.is_empty()
+    }
+
+    /// Returns the obfuscated string.
+    pub fn finish(self) -> Encrusted<String, B> {
+        self.encrusted
+    }
+
+    /// Works like [`EncrustedStringBuilder::finish`], but additionally stores a MAC of the
+    /// obfuscated data that is verified by [`Encrusted::try_decrust`].
+    #[cfg(feature = "integrity")]
+    pub fn finish_authenticated(self) -> Encrusted<String, B> {
+        let mut encrusted = self.encrusted;
+        encrusted.integrity = Some(Integrity::new(&encrusted.data, &encrusted.key));
+
+        encrusted
+    }
+}
+
+impl<B> fmt::Write for EncrustedStringBuilder<B>
+where
+    B: Backend,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write as _;
+
+    use super::*;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[test]
+    fn matches_encrusted() {
+        let mut builder = EncrustedBytesBuilder::new(0x2357_bd11);
+        for _ in 0..10 {
+            builder.append(TEST_STRING.as_bytes());
+        }
+
+        let data = Bytes::from(TEST_STRING.repeat(10).into_bytes());
+        let mut built = builder.finish();
+
+        assert_eq!(Encrusted::new(data.clone(), 0x2357_bd11).data, built.data);
+        assert_eq!(data, *built.decrust());
+    }
+
+    #[test]
+    fn string_matches_encrusted() {
+        let mut builder = EncrustedStringBuilder::new(0x2357_bd11);
+        for c in TEST_STRING.chars() {
+            builder.write_char(c).unwrap();
+        }
+
+        let mut built = builder.finish();
+
+        assert_eq!(
+            Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11).data,
+            built.data
+        );
+        assert_eq!(TEST_STRING, built.decrust().as_str());
+    }
+
+    #[test]
+    fn never_holds_plaintext() {
+        let mut builder = EncrustedBytesBuilder::new(0x2357_bd11);
+        builder.append(&[0; 1000]);
+
+        // Zeros are obfuscated to the keystream itself.
+        assert!(builder.encrusted.data.iter().any(|byte| *byte != 0));
+        assert_eq!(1000, builder.len());
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn authenticated() {
+        let mut builder = EncrustedStringBuilder::new(0x2357_bd11);
+        builder.write_str(TEST_STRING).unwrap();
+
+        let mut built = builder.finish_authenticated();
+        assert_eq!(TEST_STRING, built.try_decrust().unwrap().as_str());
+    }
+}
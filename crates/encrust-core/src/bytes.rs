@@ -0,0 +1,406 @@
+//! Byte vectors obfuscated using whole blocks of keystream at a time.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::ops::{Deref, DerefMut, Range};
+#[cfg(feature = "std")]
+use std::ops::{Deref, DerefMut, Range};
+
+use zeroize::Zeroize;
+
+#[cfg(feature = "integrity")]
+use crate::{Authenticate, IntegrityMac};
+use crate::{
+    Encrustable, Encrusted, Keystream, SeekableBackend, SmallRngBackend,
+    growth::{reserve_zeroizing, truncate_zeroizing},
+};
+
+/// Number of bytes obfuscated using each block of keystream.
+const BLOCK_SIZE: usize = 64;
+
+/// [`Encrusted`] byte vector, see [`Bytes`].
+pub type EncrustedBytes<B = SmallRngBackend> = Encrusted<Bytes, B>;
+
+/// Byte vector for use with [`Encrusted`], dereferencing to `Vec<u8>`.
+///
+/// `Bytes` obfuscates the data in blocks of 64 bytes using a single block of keystream for each,
+/// like `Vec<u8>`. As the position of every block in the keystream is known, backends implementing
+/// [`SeekableBackend`] can deobfuscate part of the buffer using [`Encrusted::decrust_range`], and
+/// obfuscate large buffers using multiple threads. `encrust_vec!` with `u8` elements and
+/// `encrust_file_bytes!` produce [`EncrustedBytes`].
+///
+/// The functions growing the buffer, such as [`Bytes::push`], zeroize the old allocation when the
+/// buffer is moved to a larger one.
+///
+/// # Example
+/// ```
+/// use encrust_core::{Bytes, EncrustedBytes};
+///
+/// let mut bytes = EncrustedBytes::new(Bytes::from(vec![1, 2, 3]), 0xc0ffee);
+/// bytes.decrust().push(4);
+/// assert_eq!(vec![1, 2, 3, 4], *bytes.decrust());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Zeroize)]
+pub struct Bytes(Vec<u8>);
+
+impl Bytes {
+    /// Returns the underlying `Vec<u8>`.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Works like [`Vec::reserve`], but zeroizes the old allocation if the buffer grows.
+    pub fn reserve(&mut self, additional: usize) {
+        reserve_zeroizing(&mut self.0, additional);
+    }
+
+    /// Works like [`Vec::push`], but zeroizes the old allocation if the buffer grows.
+    pub fn push(&mut self, value: u8) {
+        self.reserve(1);
+        self.0.push(value);
+    }
+
+    /// Works like [`Vec::insert`], but zeroizes the old allocation if the buffer grows.
+    ///
+    /// # Panics
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, element: u8) {
+        self.reserve(1);
+        self.0.insert(index, element);
+    }
+
+    /// Works like [`Vec::extend_from_slice`], but zeroizes the old allocation if the buffer grows.
+    pub fn extend_from_slice(&mut self, other: &[u8]) {
+        self.reserve(other.len());
+        self.0.extend_from_slice(other);
+    }
+
+    /// Works like [`Vec::resize`], but zeroizes the old allocation if the buffer grows, and the
+    /// removed bytes if it shrinks.
+    pub fn resize(&mut self, new_len: usize, value: u8) {
+        if new_len > self.0.len() {
+            self.reserve(new_len - self.0.len());
+            self.0.resize(new_len, value);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Works like [`Vec::truncate`], but zeroizes the removed bytes.
+    pub fn truncate(&mut self, len: usize) {
+        truncate_zeroizing(&mut self.0, len);
+    }
+
+    /// Works like [`Vec::clear`], but zeroizes the removed bytes.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl PartialEq<Vec<u8>> for Bytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<Bytes> for Vec<u8> {
+    fn eq(&self, other: &Bytes) -> bool {
+        self == &other.0
+    }
+}
+
+impl PartialEq<[u8]> for Bytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl Encrustable for Bytes {
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        // A full block of keystream is used for the last block even if it is shorter, so the
+        // keystream consumed only depends on the number of blocks.
+        let mut key = [0; BLOCK_SIZE];
+        for block in self.0.chunks_mut(BLOCK_SIZE) {
+            encrust_rng.fill(&mut key);
+            for (byte, byte_key) in block.iter_mut().zip(key.iter()) {
+                *byte ^= byte_key;
+            }
+        }
+
+        key.zeroize();
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        self.0.visit_allocations(visitor);
+    }
+}
+
+/// XORs `bytes`, starting at byte `position` of the buffer, with the matching part of the
+/// keystream. This produces the same result as `toggle_encrust` for the same bytes.
+fn toggle_at<B: SeekableBackend>(bytes: &mut [u8], position: usize, key: &B::Key) {
+    let mut encrust_rng = B::keystream(key);
+    B::seek(&mut encrust_rng, (position / BLOCK_SIZE) as u64);
+
+    // The first block is only partially used if `position` is not at the start of a block.
+    let mut offset = position % BLOCK_SIZE;
+    let mut key = [0; BLOCK_SIZE];
+    let mut remaining = bytes;
+    while !remaining.is_empty() {
+        encrust_rng.fill(&mut key);
+
+        let (block, rest) = remaining.split_at_mut(remaining.len().min(BLOCK_SIZE - offset));
+        for (byte, byte_key) in block.iter_mut().zip(&key[offset..]) {
+            *byte ^= byte_key;
+        }
+
+        remaining = rest;
+        offset = 0;
+    }
+
+    key.zeroize();
+}
+
+impl<B: SeekableBackend> Encrusted<Bytes, B> {
+    /// Deobfuscates only the bytes in `range` and returns a [`DecrustedRange`] object that can be
+    /// used to access and modify them. The rest of the buffer stays obfuscated, and the keystream
+    /// for the bytes before `range` is skipped rather than generated.
+    ///
+    /// The bytes are obfuscated again using the same key when the `DecrustedRange` object is
+    /// dropped, even if key rotation is enabled. The integrity of authenticated data is not
+    /// verified, but the MAC is updated to match any modifications.
+    ///
+    /// # Panics
+    /// Panics if `range` is out of bounds.
+    ///
+    /// # Example
+    /// ```
+    /// # #[cfg(feature = "chacha20")]
+    /// # {
+    /// use encrust_core::{Bytes, ChaCha20Backend, Encrusted};
+    ///
+    /// let asset = Bytes::from((0..=255).collect::<Vec<u8>>());
+    /// let mut asset = Encrusted::<_, ChaCha20Backend>::with_key(asset, [7; 32]);
+    /// assert_eq!(&[100, 101, 102], &*asset.decrust_range(100..103));
+    /// # }
+    /// ```
+    pub fn decrust_range(&mut self, range: Range<usize>) -> DecrustedRange<'_, B> {
+        toggle_at::<B>(&mut self.data.0[range.clone()], range.start, &self.key);
+
+        DecrustedRange {
+            encrusted: self,
+            range,
+        }
+    }
+
+    /// Works like [`Encrusted::with_key`], but obfuscates the data using multiple threads, one for
+    /// each part of the buffer. The result is identical to using [`Encrusted::with_key`].
+    ///
+    /// The number of threads is given by [`std::thread::available_parallelism`].
+    #[cfg(feature = "std")]
+    pub fn with_key_parallel(data: Bytes, key: B::Key) -> Self
+    where
+        B::Key: Sync,
+    {
+        let threads = std::thread::available_parallelism().map_or(1, std::num::NonZero::get);
+        // The data is obfuscated in place after moving it into the `Encrusted` object.
+        let mut encrusted = Self::with_key(Bytes::default(), key);
+        encrusted.data = data;
+
+        let part_size = encrusted
+            .data
+            .len()
+            .div_ceil(threads)
+            .next_multiple_of(BLOCK_SIZE)
+            // Empty buffers would otherwise be split into parts of zero bytes.
+            .max(BLOCK_SIZE);
+        let key = &encrusted.key;
+
+        std::thread::scope(|scope| {
+            for (index, part) in encrusted.data.0.chunks_mut(part_size).enumerate() {
+                scope.spawn(move || toggle_at::<B>(part, index * part_size, key));
+            }
+        });
+
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        encrusted.hardened.update(&mut encrusted.data);
+
+        encrusted
+    }
+}
+
+/// Type used to access part of an [`EncrustedBytes`] buffer. Use [`Encrusted::decrust_range`] to
+/// create `DecrustedRange` objects.
+///
+/// When the `DecrustedRange` object is dropped, the bytes are obfuscated again.
+pub struct DecrustedRange<'a, B>
+where
+    B: SeekableBackend,
+{
+    encrusted: &'a mut Encrusted<Bytes, B>,
+    range: Range<usize>,
+}
+
+impl<B> Deref for DecrustedRange<'_, B>
+where
+    B: SeekableBackend,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.encrusted.data.0[self.range.clone()]
+    }
+}
+
+impl<B> DerefMut for DecrustedRange<'_, B>
+where
+    B: SeekableBackend,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.encrusted.data.0[self.range.clone()]
+    }
+}
+
+impl<B> Drop for DecrustedRange<'_, B>
+where
+    B: SeekableBackend,
+{
+    fn drop(&mut self) {
+        let encrusted = &mut *self.encrusted;
+        toggle_at::<B>(
+            &mut encrusted.data.0[self.range.clone()],
+            self.range.start,
+            &encrusted.key,
+        );
+
+        #[cfg(feature = "integrity")]
+        if let Some(integrity) = &mut encrusted.integrity {
+            integrity.update(&encrusted.data, &encrusted.key);
+        }
+    }
+}
+
+#[cfg(feature = "integrity")]
+impl Authenticate for Bytes {
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        mac.update(&(self.0.len() as u64).to_le_bytes());
+        mac.update(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+
+    #[test]
+    fn block_keystream() {
+        let data = (0..1000u16).map(|n| n.to_le_bytes()[0]).collect::<Vec<_>>();
+        let mut encrusted = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+
+        let mut keystream = vec![0; 1000_usize.next_multiple_of(BLOCK_SIZE)];
+        SmallRngBackend::keystream(&0x2357_bd11).fill(&mut keystream);
+        let expected = data
+            .iter()
+            .zip(&keystream)
+            .map(|(byte, key)| byte ^ key)
+            .collect::<Vec<_>>();
+
+        assert_eq!(expected, encrusted.data);
+        assert_eq!(data, *encrusted.decrust());
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn decrust_range() {
+        use crate::ChaCha20Backend;
+
+        let data = (0..1000u16).map(|n| n.to_le_bytes()[0]).collect::<Vec<_>>();
+        let mut encrusted =
+            Encrusted::<_, ChaCha20Backend>::with_key(Bytes::from(data.clone()), [7; 32]);
+        let obfuscated = encrusted.data.clone();
+
+        for range in [0..0, 0..10, 60..70, 64..128, 100..1000, 999..1000] {
+            let decrusted = encrusted.decrust_range(range.clone());
+            assert_eq!(&data[range.clone()], &*decrusted);
+            drop(decrusted);
+
+            assert_eq!(obfuscated, encrusted.data);
+        }
+
+        encrusted.decrust_range(500..502).copy_from_slice(&[1, 2]);
+        assert_eq!(&[1, 2], &encrusted.decrust()[500..502]);
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn decrust_range_aes() {
+        use crate::AesCtrBackend;
+
+        let data = (0..1000u16).map(|n| n.to_le_bytes()[0]).collect::<Vec<_>>();
+        let mut encrusted =
+            Encrusted::<_, AesCtrBackend>::with_key(Bytes::from(data.clone()), [7; 32]);
+
+        assert_eq!(&data[130..700], &*encrusted.decrust_range(130..700));
+    }
+
+    #[cfg(all(feature = "chacha20", feature = "std"))]
+    #[test]
+    fn parallel() {
+        use crate::ChaCha20Backend;
+
+        let data = Bytes::from(
+            (0..100_000u32)
+                .map(|n| n.to_le_bytes()[0])
+                .collect::<Vec<_>>(),
+        );
+        let sequential = Encrusted::<_, ChaCha20Backend>::with_key(data.clone(), [7; 32]);
+        let mut parallel =
+            Encrusted::<_, ChaCha20Backend>::with_key_parallel(data.clone(), [7; 32]);
+
+        assert_eq!(sequential.data, parallel.data);
+        assert_eq!(data, *parallel.decrust());
+    }
+
+    #[cfg(all(feature = "chacha20", feature = "std"))]
+    #[test]
+    fn parallel_empty() {
+        use crate::ChaCha20Backend;
+
+        let mut parallel =
+            Encrusted::<_, ChaCha20Backend>::with_key_parallel(Bytes::default(), [7; 32]);
+        assert!(parallel.decrust().is_empty());
+    }
+
+    #[test]
+    fn empty() {
+        let mut encrusted = EncrustedBytes::new(Bytes::default(), 0x2357_bd11);
+        assert!(encrusted.decrust().is_empty());
+    }
+}
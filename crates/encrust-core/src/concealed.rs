@@ -0,0 +1,320 @@
+//! Byte vectors and strings that hide their heap pointer, length and capacity while obfuscated.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+#[cfg(not(feature = "std"))]
+use core::{
+    fmt,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+#[cfg(feature = "std")]
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+use zeroize::Zeroize;
+
+use crate::{Encrustable, Keystream};
+
+/// The smallest allocation used by [`ConcealedBytes`]. Allocations are rounded up to the next power
+/// of two, but never smaller than this.
+const MIN_BUCKET: usize = 32;
+
+/// Fixed-size byte vector hiding its pointer, length and capacity while obfuscated.
+///
+/// `Vec<u8>` only has its contents obfuscated, so the pointer, length and capacity are visible next
+/// to the obfuscated data, revealing the size of the secret and making it easy to find by scanning
+/// memory for plausible vectors. `ConcealedBytes` pads its data to a power-of-two sized allocation,
+/// obfuscating the padding as well, and obfuscates its pointer, length and capacity together with
+/// the data.
+///
+/// As the allocation cannot be found while obfuscated, [`Encrusted`](crate::Encrusted) deobfuscates
+/// `ConcealedBytes` before zeroizing and dropping it, and it does not implement `Clone` or support
+/// integrity checks. `ConcealedBytes` is never hardened by the `harden` feature before it has been
+/// deobfuscated the first time.
+///
+/// # Example
+/// ```
+/// use encrust_core::{ConcealedBytes, Encrusted};
+///
+/// let mut key = Encrusted::new(ConcealedBytes::from(vec![1, 2, 3, 4]), 0xc0ffee);
+/// assert_eq!(&[1, 2, 3, 4], &key.decrust()[..]);
+/// ```
+pub struct ConcealedBytes {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+    concealed: bool,
+}
+
+// SAFETY:
+// `ConcealedBytes` owns its allocation exclusively, like `Vec<u8>`.
+unsafe impl Send for ConcealedBytes {}
+
+// SAFETY:
+// The data can only be modified through a mutable reference.
+unsafe impl Sync for ConcealedBytes {}
+
+impl ConcealedBytes {
+    /// Creates a new buffer containing a copy of `bytes`.
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let bucket = bytes.len().next_power_of_two().max(MIN_BUCKET);
+
+        let mut buffer = Vec::with_capacity(bucket);
+        buffer.extend_from_slice(bytes);
+        // The padding is filled with zeros so all of the allocation is initialized and can be
+        // obfuscated.
+        buffer.resize(buffer.capacity(), 0);
+
+        let mut buffer = ManuallyDrop::new(buffer);
+
+        Self {
+            ptr: buffer.as_mut_ptr(),
+            len: bytes.len(),
+            capacity: buffer.capacity(),
+            concealed: false,
+        }
+    }
+
+    /// Toggles the obfuscation of the pointer, length and capacity.
+    fn toggle_fields(&mut self, masks: &[usize; 3]) {
+        self.ptr = self.ptr.map_addr(|addr| addr ^ masks[0]);
+        self.len ^= masks[1];
+        self.capacity ^= masks[2];
+    }
+
+    /// Returns the whole allocation, including the padding.
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        debug_assert!(!self.concealed, "Accessing obfuscated ConcealedBytes");
+
+        // SAFETY:
+        // The pointer and capacity are valid while the fields are not obfuscated, and all of the
+        // allocation is initialized.
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.capacity) }
+    }
+}
+
+fn mask(encrust_rng: &mut impl Keystream) -> usize {
+    let mut bytes = [0; size_of::<usize>()];
+    encrust_rng.fill(&mut bytes);
+
+    usize::from_le_bytes(bytes)
+}
+
+impl From<&[u8]> for ConcealedBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self::from_slice(bytes)
+    }
+}
+
+impl From<Vec<u8>> for ConcealedBytes {
+    /// Moves the bytes into a padded allocation, zeroizing the `Vec`.
+    fn from(mut bytes: Vec<u8>) -> Self {
+        let concealed = Self::from_slice(&bytes);
+        bytes.zeroize();
+
+        concealed
+    }
+}
+
+impl Deref for ConcealedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        debug_assert!(!self.concealed, "Accessing obfuscated ConcealedBytes");
+
+        // SAFETY:
+        // The pointer and length are valid while the fields are not obfuscated, which is the case
+        // whenever the data is accessible.
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for ConcealedBytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        let len = self.len;
+
+        &mut self.buffer_mut()[..len]
+    }
+}
+
+impl fmt::Debug for ConcealedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcealedBytes").finish_non_exhaustive()
+    }
+}
+
+impl Encrustable for ConcealedBytes {
+    const DECRUST_BEFORE_DROP: bool = true;
+
+    /// The masks for the pointer, length and capacity are taken from the start of the keystream,
+    /// followed by the keystream for the whole allocation.
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        let mut masks = [mask(encrust_rng), mask(encrust_rng), mask(encrust_rng)];
+        let was_concealed = self.concealed;
+
+        if was_concealed {
+            self.toggle_fields(&masks);
+            self.concealed = false;
+        }
+
+        // Encrusting 16 bytes at a time, like `String`.
+        let mut key: [u8; 16] = [0; 16];
+        for chunk in self.buffer_mut().chunks_mut(16) {
+            encrust_rng.fill(&mut key);
+            for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
+                *byte ^= byte_key;
+            }
+        }
+
+        if !was_concealed {
+            self.toggle_fields(&masks);
+            self.concealed = true;
+        }
+
+        masks.zeroize();
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        if !self.concealed {
+            visitor(self.ptr, self.capacity);
+        }
+    }
+}
+
+impl Zeroize for ConcealedBytes {
+    /// Zeroizes the whole allocation, including the padding. Nothing is zeroized if the data is
+    /// obfuscated, as the allocation cannot be found.
+    fn zeroize(&mut self) {
+        if !self.concealed {
+            self.buffer_mut().zeroize();
+            self.len = 0;
+        }
+    }
+}
+
+impl Drop for ConcealedBytes {
+    /// Zeroizes and frees the allocation. If the data is obfuscated, the allocation is leaked as
+    /// it cannot be found.
+    fn drop(&mut self) {
+        if !self.concealed {
+            self.zeroize();
+
+            // SAFETY:
+            // The pointer and capacity were taken from a `Vec<u8>`, and the allocation is not used
+            // after this. The length is set to zero as `u8` does not need to be dropped.
+            drop(unsafe { Vec::from_raw_parts(self.ptr, 0, self.capacity) });
+        }
+    }
+}
+
+/// String hiding its pointer, length and capacity while obfuscated, see [`ConcealedBytes`].
+///
+/// # Example
+/// ```
+/// use encrust_core::{ConcealedString, Encrusted};
+///
+/// let mut password = Encrusted::new(ConcealedString::from("A secret password"), 0xc0ffee);
+/// assert_eq!("A secret password", &**password.decrust());
+/// ```
+#[derive(Debug, Zeroize)]
+pub struct ConcealedString(ConcealedBytes);
+
+impl From<&str> for ConcealedString {
+    fn from(string: &str) -> Self {
+        Self(ConcealedBytes::from_slice(string.as_bytes()))
+    }
+}
+
+impl From<String> for ConcealedString {
+    /// Moves the string into a padded allocation, zeroizing the `String`.
+    fn from(string: String) -> Self {
+        Self(ConcealedBytes::from(string.into_bytes()))
+    }
+}
+
+impl Deref for ConcealedString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY:
+        // The bytes were valid UTF-8 when the string was created, and can only be modified through
+        // `DerefMut` as a `str`, which maintains this invariant.
+        unsafe { core::str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+impl DerefMut for ConcealedString {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY:
+        // See `deref`.
+        unsafe { core::str::from_utf8_unchecked_mut(&mut self.0) }
+    }
+}
+
+impl Encrustable for ConcealedString {
+    const DECRUST_BEFORE_DROP: bool = true;
+
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        // SAFETY:
+        // The string is only accessed after being deobfuscated again.
+        unsafe {
+            self.0.toggle_encrust(encrust_rng);
+        }
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        self.0.visit_allocations(visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[test]
+    fn conceal_fields() {
+        let concealed = ConcealedString::from(TEST_STRING);
+        let ptr = concealed.0.ptr;
+        let capacity = concealed.0.capacity;
+        assert_eq!(64, capacity);
+
+        let mut encrusted = Encrusted::new(concealed, 0x2357_bd11_1317_1d1f);
+
+        assert!(encrusted.data.0.concealed);
+        assert_ne!(ptr.addr(), encrusted.data.0.ptr.addr());
+        assert_ne!(TEST_STRING.len(), encrusted.data.0.len);
+        assert_ne!(capacity, encrusted.data.0.capacity);
+
+        {
+            let decrusted = encrusted.decrust();
+            assert_eq!(TEST_STRING, &**decrusted);
+            assert_eq!(ptr, decrusted.0.ptr);
+        }
+
+        assert!(encrusted.data.0.concealed);
+        assert_ne!(ptr.addr(), encrusted.data.0.ptr.addr());
+    }
+
+    #[test]
+    fn buckets() {
+        assert_eq!(32, ConcealedBytes::from_slice(&[]).capacity);
+        assert_eq!(32, ConcealedBytes::from_slice(&[1; 32]).capacity);
+        assert_eq!(64, ConcealedBytes::from_slice(&[1; 33]).capacity);
+        assert_eq!(1024, ConcealedBytes::from_slice(&[1; 1000]).capacity);
+    }
+
+    #[test]
+    fn modify() {
+        let mut encrusted = Encrusted::new(ConcealedBytes::from(vec![1, 2, 3]), 0x2357_bd11);
+        encrusted.decrust()[1] = 5;
+
+        assert_eq!(&[1, 5, 3], &encrusted.decrust()[..]);
+    }
+}
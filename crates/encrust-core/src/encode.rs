@@ -0,0 +1,274 @@
+//! Encoding obfuscated data as bytes, used to serialize and seal [`Encrusted`] objects.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+use zeroize::Zeroize;
+
+use crate::{Backend, Bytes, Encrustable, Encrusted};
+
+/// Trait required to serialize and seal [`Encrusted`] objects holding a data type. It encodes the
+/// obfuscated data into a platform independent sequence of bytes, and decodes it again without
+/// deobfuscating it.
+///
+/// If it is avoidable, do not implement this manually, but use the derive macro to generate the
+/// implementation. Every part of the data modified by [`Encrustable::toggle_encrust`] must be
+/// encoded.
+pub trait EncodeObfuscated: Sized {
+    /// Appends the encoded data in `self` to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decodes data encoded by [`EncodeObfuscated::encode`] from the start of `input`, advancing
+    /// `input` past it. Returns `None` if `input` does not contain valid encoded data.
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+
+    /// Set to `true` by types that must be checked by `check_decrusted` after being decoded.
+    /// The derive macro sets this if any field requires it.
+    #[doc(hidden)]
+    const CHECK_DECRUSTED: bool = false;
+
+    /// Called with the deobfuscated data after decoding, returning `false` if the data is not
+    /// valid, such as strings that are not valid UTF-8.
+    #[doc(hidden)]
+    fn check_decrusted(&self) -> bool {
+        true
+    }
+}
+
+/// Removes and returns the first `len` bytes of `input`.
+pub(crate) fn take<'input>(input: &mut &'input [u8], len: usize) -> Option<&'input [u8]> {
+    let (taken, rest) = input.split_at_checked(len)?;
+    *input = rest;
+
+    Some(taken)
+}
+
+/// Decodes a length encoded as a `u64`.
+fn decode_len(input: &mut &[u8]) -> Option<usize> {
+    usize::try_from(u64::decode(input)?).ok()
+}
+
+macro_rules! encode_number {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl EncodeObfuscated for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> Option<Self> {
+                    let mut bytes = [0; size_of::<Self>()];
+                    bytes.copy_from_slice(take(input, size_of::<Self>())?);
+
+                    Some(Self::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+encode_number!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128);
+
+// `usize` and `isize` are encoded as 64-bit numbers so data can be exchanged between platforms
+// with different pointer widths.
+impl EncodeObfuscated for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Self::try_from(u64::decode(input)?).ok()
+    }
+}
+
+impl EncodeObfuscated for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Self::try_from(i64::decode(input)?).ok()
+    }
+}
+
+impl EncodeObfuscated for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?.to_vec();
+
+        // SAFETY:
+        // The bytes are obfuscated, and are only accessed as a `String` after being deobfuscated
+        // and checked by `check_decrusted`.
+        Some(unsafe { Self::from_utf8_unchecked(bytes) })
+    }
+
+    const CHECK_DECRUSTED: bool = true;
+
+    fn check_decrusted(&self) -> bool {
+        core::str::from_utf8(self.as_bytes()).is_ok()
+    }
+}
+
+impl EncodeObfuscated for Bytes {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = decode_len(input)?;
+
+        Some(Self::from(take(input, len)?))
+    }
+}
+
+impl<T, const N: usize> EncodeObfuscated for [T; N]
+where
+    T: EncodeObfuscated,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        for element in self {
+            element.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let elements = (0..N)
+            .map(|_| T::decode(input))
+            .collect::<Option<Vec<_>>>()?;
+
+        elements.try_into().ok()
+    }
+
+    const CHECK_DECRUSTED: bool = T::CHECK_DECRUSTED;
+
+    fn check_decrusted(&self) -> bool {
+        self.iter().all(T::check_decrusted)
+    }
+}
+
+impl<T> EncodeObfuscated for Vec<T>
+where
+    T: EncodeObfuscated,
+{
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+
+        for element in self {
+            element.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = decode_len(input)?;
+
+        // The length is not trusted. Elements that are not zero-sized take up at least one byte
+        // each, while zero-sized elements would allow a short input to claim any length.
+        if len > input.len() || (size_of::<T>() == 0 && len > 0) {
+            return None;
+        }
+
+        let mut vec = Vec::with_capacity(len);
+        for _ in 0..len {
+            vec.push(T::decode(input)?);
+        }
+
+        Some(vec)
+    }
+
+    const CHECK_DECRUSTED: bool = T::CHECK_DECRUSTED;
+
+    fn check_decrusted(&self) -> bool {
+        self.iter().all(T::check_decrusted)
+    }
+}
+
+impl<T, B> Encrusted<T, B>
+where
+    T: Encrustable + EncodeObfuscated + Zeroize,
+    B: Backend,
+{
+    /// Returns the obfuscated data encoded by [`EncodeObfuscated`].
+    pub(crate) fn encode_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.data.encode(&mut data);
+
+        data
+    }
+
+    /// Creates an `Encrusted` object from `data` encoded by [`Encrusted::encode_data`], the key it
+    /// is obfuscated with and the version of the backend's algorithm used to obfuscate it. Returns
+    /// `None` if `data` is not valid or `version` is not supported, in which case `key` is
+    /// zeroized.
+    ///
+    /// Types that can hold invalid data while obfuscated, such as strings, are deobfuscated in
+    /// place to check that the data is valid.
+    pub(crate) fn from_encoded_data(data: &[u8], mut key: B::Key, version: u8) -> Option<Self> {
+        let Some(decoded) = decode_all(data) else {
+            key.zeroize();
+            return None;
+        };
+
+        Self::from_versioned(decoded, key, version)?.check_decoded()
+    }
+
+    /// Checks that decoded data is valid once deobfuscated, see
+    /// [`EncodeObfuscated::check_decrusted`].
+    pub(crate) fn check_decoded(mut self) -> Option<Self> {
+        if T::CHECK_DECRUSTED {
+            self.expose();
+            let valid = self.data.check_decrusted();
+            self.conceal();
+
+            if !valid {
+                return None;
+            }
+        }
+
+        Some(self)
+    }
+}
+
+/// Decodes `data` encoded by [`EncodeObfuscated::encode`], requiring all of it to be used.
+pub(crate) fn decode_all<T>(data: &[u8]) -> Option<T>
+where
+    T: EncodeObfuscated,
+{
+    let mut input = data;
+
+    T::decode(&mut input).filter(|_| input.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vec_roundtrip() {
+        let vec = vec![0x2357_bd11_u64, 42];
+
+        let mut encoded = Vec::new();
+        vec.encode(&mut encoded);
+
+        assert_eq!(Some(vec), Vec::<u64>::decode(&mut encoded.as_slice()));
+    }
+
+    #[test]
+    fn vec_length_exceeds_input() {
+        let mut encoded = Vec::new();
+        u64::MAX.encode(&mut encoded);
+        0_u64.encode(&mut encoded);
+
+        assert_eq!(None, Vec::<u64>::decode(&mut encoded.as_slice()));
+        assert_eq!(None, Vec::<[u8; 0]>::decode(&mut encoded.as_slice()));
+
+        let mut encoded = Vec::new();
+        1_u64.encode(&mut encoded);
+        assert_eq!(None, Vec::<[u8; 0]>::decode(&mut encoded.as_slice()));
+    }
+}
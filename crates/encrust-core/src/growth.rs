@@ -0,0 +1,253 @@
+//! Growing deobfuscated vectors and strings without leaving copies of the data in freed memory.
+//!
+//! When a `Vec` or `String` grows past its capacity, the data is moved to a new allocation and the
+//! old one is freed as-is. The functions here are available on [`Decrusted`] vectors and strings,
+//! taking precedence over the functions of the same name reached through `DerefMut`. They move the
+//! data to a new allocation themselves, zeroizing the old one before it is freed.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+use zeroize::Zeroize;
+
+use crate::{Backend, Decrusted, Encrustable};
+
+/// Makes sure `vec` has room for at least `additional` more elements. If it has to grow, the
+/// elements are moved to a new allocation of at least twice the size, and the old allocation is
+/// zeroized before it is freed.
+///
+/// # Panics
+/// Panics if the new capacity overflows `usize`.
+pub(crate) fn reserve_zeroizing<T: Zeroize>(vec: &mut Vec<T>, additional: usize) {
+    let required = vec
+        .len()
+        .checked_add(additional)
+        .expect("capacity overflow");
+    if required <= vec.capacity() {
+        return;
+    }
+
+    let mut grown = Vec::with_capacity(required.max(vec.capacity() * 2));
+    grown.append(vec);
+
+    // `vec` is empty, so this zeroizes its whole allocation.
+    vec.zeroize();
+    *vec = grown;
+}
+
+/// Zeroizes and removes the elements of `vec` after the first `len` elements.
+pub(crate) fn truncate_zeroizing<T: Zeroize>(vec: &mut Vec<T>, len: usize) {
+    if len < vec.len() {
+        vec[len..].iter_mut().for_each(Zeroize::zeroize);
+        vec.truncate(len);
+    }
+}
+
+impl<T, B> Decrusted<'_, Vec<T>, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    /// Works like [`Vec::reserve`], but zeroizes the old allocation if the vector grows.
+    pub fn reserve(&mut self, additional: usize) {
+        reserve_zeroizing(self, additional);
+    }
+
+    /// Works like [`Vec::push`], but zeroizes the old allocation if the vector grows.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let mut pin = Encrusted::new(vec![1u8, 2, 3], 0xc0ffee);
+    /// pin.decrust().push(4);
+    /// assert_eq!(vec![1, 2, 3, 4], *pin.decrust());
+    /// ```
+    pub fn push(&mut self, value: T) {
+        self.reserve(1);
+        (**self).push(value);
+    }
+
+    /// Works like [`Vec::insert`], but zeroizes the old allocation if the vector grows.
+    ///
+    /// # Panics
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, element: T) {
+        self.reserve(1);
+        (**self).insert(index, element);
+    }
+
+    /// Works like [`Vec::extend_from_slice`], but zeroizes the old allocation if the vector grows.
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.reserve(other.len());
+        (**self).extend_from_slice(other);
+    }
+
+    /// Works like [`Vec::resize`], but zeroizes the old allocation if the vector grows, and the
+    /// removed elements if it shrinks.
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        if new_len > self.len() {
+            self.reserve(new_len - self.len());
+            (**self).resize(new_len, value);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Works like [`Vec::truncate`], but zeroizes the removed elements before they are dropped.
+    pub fn truncate(&mut self, len: usize) {
+        truncate_zeroizing(self, len);
+    }
+
+    /// Works like [`Vec::clear`], but zeroizes the removed elements before they are dropped.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<B> Decrusted<'_, String, B>
+where
+    B: Backend,
+{
+    /// Returns the bytes of the string.
+    fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        // SAFETY:
+        // The bytes are only moved, zeroized or truncated at character boundaries, so the string
+        // remains valid UTF-8.
+        unsafe { self.as_mut_vec() }
+    }
+
+    /// Works like [`String::reserve`], but zeroizes the old allocation if the string grows.
+    pub fn reserve(&mut self, additional: usize) {
+        reserve_zeroizing(self.bytes_mut(), additional);
+    }
+
+    /// Works like [`String::push`], but zeroizes the old allocation if the string grows.
+    pub fn push(&mut self, ch: char) {
+        self.reserve(ch.len_utf8());
+        (**self).push(ch);
+    }
+
+    /// Works like [`String::push_str`], but zeroizes the old allocation if the string grows.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let mut password = Encrusted::new("A secret".to_string(), 0xc0ffee);
+    /// password.decrust().push_str(" password");
+    /// assert_eq!("A secret password", password.decrust().as_str());
+    /// ```
+    pub fn push_str(&mut self, string: &str) {
+        self.reserve(string.len());
+        (**self).push_str(string);
+    }
+
+    /// Works like [`String::insert`], but zeroizes the old allocation if the string grows.
+    ///
+    /// # Panics
+    /// Panics if `index` is not a character boundary.
+    pub fn insert(&mut self, index: usize, ch: char) {
+        self.reserve(ch.len_utf8());
+        (**self).insert(index, ch);
+    }
+
+    /// Works like [`String::insert_str`], but zeroizes the old allocation if the string grows.
+    ///
+    /// # Panics
+    /// Panics if `index` is not a character boundary.
+    pub fn insert_str(&mut self, index: usize, string: &str) {
+        self.reserve(string.len());
+        (**self).insert_str(index, string);
+    }
+
+    /// Works like [`String::truncate`], but zeroizes the removed bytes.
+    ///
+    /// # Panics
+    /// Panics if `new_len` is not a character boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        assert!(
+            self.is_char_boundary(new_len),
+            "new_len is not a char boundary"
+        );
+
+        truncate_zeroizing(self.bytes_mut(), new_len);
+    }
+
+    /// Works like [`String::clear`], but zeroizes the removed bytes.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[test]
+    fn reserve_moves_elements() {
+        let mut vec = vec![1u32, 2, 3];
+        let capacity = vec.capacity();
+
+        reserve_zeroizing(&mut vec, 100);
+        assert!(vec.capacity() >= 103);
+        assert_eq!(vec![1, 2, 3], vec);
+
+        let ptr = vec.as_ptr();
+        reserve_zeroizing(&mut vec, 100);
+        assert_eq!(ptr, vec.as_ptr());
+        assert!(vec.capacity() > capacity);
+    }
+
+    #[test]
+    fn vec_growth() {
+        let mut encrusted = Encrusted::new(Vec::<u16>::new(), 0x2357_bd11);
+
+        {
+            let mut decrusted = encrusted.decrust();
+            for n in 0..100 {
+                decrusted.push(n);
+            }
+            decrusted.insert(0, 1000);
+            decrusted.extend_from_slice(&[2000, 3000]);
+        }
+
+        let mut expected = vec![1000];
+        expected.extend(0..100);
+        expected.extend([2000, 3000]);
+        assert_eq!(expected, *encrusted.decrust());
+
+        encrusted.decrust().truncate(1);
+        assert_eq!(vec![1000], *encrusted.decrust());
+
+        encrusted.decrust().resize(3, 7);
+        assert_eq!(vec![1000, 7, 7], *encrusted.decrust());
+    }
+
+    #[test]
+    fn string_growth() {
+        let mut encrusted = Encrusted::new(String::new(), 0x2357_bd11);
+
+        {
+            let mut decrusted = encrusted.decrust();
+            decrusted.push_str(&TEST_STRING[1..]);
+            decrusted.insert(0, 'T');
+            decrusted.push('!');
+            decrusted.insert_str(0, ">> ");
+        }
+
+        assert_eq!(format!(">> {TEST_STRING}!"), encrusted.decrust().as_str());
+
+        encrusted.decrust().clear();
+        assert!(encrusted.decrust().is_empty());
+    }
+}
@@ -0,0 +1,404 @@
+//! Byte buffers and strings allocated in their own memory pages, surrounded by guard pages.
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::OnceLock,
+};
+
+use zeroize::Zeroize;
+
+use crate::{Encrustable, Keystream};
+
+fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+
+    *PAGE_SIZE.get_or_init(|| {
+        // SAFETY:
+        // `sysconf` does not access any memory in this process.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+        usize::try_from(page_size).unwrap_or(4096)
+    })
+}
+
+/// Fixed-size byte buffer stored in dedicated memory pages, separated from other allocations by
+/// inaccessible guard pages.
+///
+/// When used with [`Encrusted`](crate::Encrusted), the pages holding the data are made
+/// inaccessible using `PROT_NONE` while the data is obfuscated, and are only readable and writable
+/// while the data is deobfuscated. Reading out of bounds of other heap allocations can therefore
+/// not reveal the obfuscated data, and reading or writing past the end of the buffer crashes the
+/// process instead of accessing other data.
+///
+/// The pages are marked with `MADV_WIPEONFORK`, so that children created using `fork` see zeroed
+/// memory instead of the data.
+///
+/// The data is placed at the end of its pages, next to the trailing guard page. As the buffer
+/// cannot be read while obfuscated, `GuardedBytes` does not implement `Clone` and cannot be used
+/// with [`Encrusted::decrust_copy`](crate::Encrusted::decrust_copy) or integrity checks.
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, GuardedBytes};
+///
+/// let mut key = Encrusted::new(GuardedBytes::from(vec![1, 2, 3, 4]), 0xc0ffee);
+/// assert_eq!(&[1, 2, 3, 4], &key.decrust()[..]);
+/// ```
+pub struct GuardedBytes {
+    /// Start of the mapping, including the guard pages.
+    mapping: NonNull<u8>,
+    /// Size of the data pages, not including the guard pages.
+    data_pages: usize,
+    len: usize,
+    protected: bool,
+}
+
+// SAFETY:
+// `GuardedBytes` owns its mapping exclusively, like `Vec<u8>` owns its allocation.
+unsafe impl Send for GuardedBytes {}
+
+// SAFETY:
+// The data can only be modified through a mutable reference.
+unsafe impl Sync for GuardedBytes {}
+
+impl GuardedBytes {
+    /// Creates a new buffer containing a copy of `bytes`.
+    ///
+    /// # Panics
+    /// Panics if the memory cannot be mapped.
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let page_size = page_size();
+        let data_pages = bytes.len().max(1).next_multiple_of(page_size);
+        let mapping_size = data_pages + 2 * page_size;
+
+        // SAFETY:
+        // Creating a new anonymous mapping does not affect any existing memory.
+        let mapping = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapping_size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        assert!(
+            mapping != libc::MAP_FAILED,
+            "Unable to map memory for guarded bytes: {}",
+            std::io::Error::last_os_error()
+        );
+
+        // SAFETY:
+        // The mapping is only used by this buffer, and wiping it only affects children. Errors are
+        // ignored, as `MADV_WIPEONFORK` is not supported by older kernels.
+        unsafe {
+            libc::madvise(mapping, mapping_size, libc::MADV_WIPEONFORK);
+        }
+
+        let mut guarded = Self {
+            mapping: NonNull::new(mapping.cast()).expect("mmap does not return null on success"),
+            data_pages,
+            len: bytes.len(),
+            protected: true,
+        };
+
+        guarded.set_protected(false);
+        guarded.copy_from_slice(bytes);
+
+        guarded
+    }
+
+    fn data_pages_ptr(&self) -> *mut u8 {
+        // SAFETY:
+        // The data pages start right after the leading guard page, inside the mapping.
+        unsafe { self.mapping.as_ptr().add(page_size()) }
+    }
+
+    fn data_ptr(&self) -> *mut u8 {
+        // SAFETY:
+        // `len` is never larger than the size of the data pages.
+        unsafe { self.data_pages_ptr().add(self.data_pages - self.len) }
+    }
+
+    fn set_protected(&mut self, protected: bool) {
+        let protection = if protected {
+            libc::PROT_NONE
+        } else {
+            libc::PROT_READ | libc::PROT_WRITE
+        };
+
+        // SAFETY:
+        // The data pages are owned by this buffer, and are only accessed through it.
+        let result =
+            unsafe { libc::mprotect(self.data_pages_ptr().cast(), self.data_pages, protection) };
+
+        assert!(
+            result == 0,
+            "Unable to change the protection of guarded bytes: {}",
+            std::io::Error::last_os_error()
+        );
+
+        self.protected = protected;
+    }
+}
+
+impl From<&[u8]> for GuardedBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self::from_slice(bytes)
+    }
+}
+
+impl From<Vec<u8>> for GuardedBytes {
+    /// Moves the bytes into guarded memory, zeroizing the `Vec`.
+    fn from(mut bytes: Vec<u8>) -> Self {
+        let guarded = Self::from_slice(&bytes);
+        bytes.zeroize();
+
+        guarded
+    }
+}
+
+impl Deref for GuardedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY:
+        // The data is only accessible through this buffer. If the data pages are protected, the
+        // buffer is obfuscated by `Encrusted` and not accessible.
+        unsafe { std::slice::from_raw_parts(self.data_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for GuardedBytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY:
+        // See `deref`.
+        unsafe { std::slice::from_raw_parts_mut(self.data_ptr(), self.len) }
+    }
+}
+
+impl fmt::Debug for GuardedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GuardedBytes")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encrustable for GuardedBytes {
+    /// Obfuscates the data and protects the data pages, or unprotects the data pages and
+    /// deobfuscates the data, depending on the current state.
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        let protect = !self.protected;
+        if self.protected {
+            self.set_protected(false);
+        }
+
+        let mut key: [u8; 16] = [0; 16];
+        for chunk in self.chunks_mut(16) {
+            encrust_rng.fill(&mut key);
+            for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
+                *byte ^= byte_key;
+            }
+        }
+
+        if protect {
+            self.set_protected(true);
+        }
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        visitor(self.data_pages_ptr(), self.data_pages);
+    }
+}
+
+impl Zeroize for GuardedBytes {
+    fn zeroize(&mut self) {
+        if self.protected {
+            self.set_protected(false);
+        }
+
+        self.deref_mut().zeroize();
+    }
+}
+
+impl Drop for GuardedBytes {
+    fn drop(&mut self) {
+        self.zeroize();
+
+        // SAFETY:
+        // The mapping is owned by this buffer and not used after this.
+        unsafe {
+            libc::munmap(
+                self.mapping.as_ptr().cast(),
+                self.data_pages + 2 * page_size(),
+            );
+        }
+    }
+}
+
+/// String stored in dedicated memory pages, see [`GuardedBytes`].
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, GuardedString};
+///
+/// let mut password = Encrusted::new(GuardedString::from("A secret password"), 0xc0ffee);
+/// assert_eq!("A secret password", &**password.decrust());
+/// ```
+#[derive(Debug, Zeroize)]
+pub struct GuardedString(GuardedBytes);
+
+impl From<&str> for GuardedString {
+    fn from(string: &str) -> Self {
+        Self(GuardedBytes::from_slice(string.as_bytes()))
+    }
+}
+
+impl From<String> for GuardedString {
+    /// Moves the string into guarded memory, zeroizing the `String`.
+    fn from(string: String) -> Self {
+        Self(GuardedBytes::from(string.into_bytes()))
+    }
+}
+
+impl Deref for GuardedString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY:
+        // The bytes were valid UTF-8 when the string was created, and can only be modified through
+        // `DerefMut` as a `str`, which maintains this invariant.
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+}
+
+impl DerefMut for GuardedString {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY:
+        // See `deref`.
+        unsafe { std::str::from_utf8_unchecked_mut(&mut self.0) }
+    }
+}
+
+impl Encrustable for GuardedString {
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        // SAFETY:
+        // The string is only accessed after being deobfuscated again.
+        unsafe {
+            self.0.toggle_encrust(encrust_rng);
+        }
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        self.0.visit_allocations(visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    /// Returns the permissions of the mapping containing `address`.
+    fn permissions(address: usize) -> String {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+
+        for line in maps.lines() {
+            let mut fields = line.split(' ');
+            let (Some(range), Some(permissions)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let Some((start, end)) = range.split_once('-') else {
+                continue;
+            };
+
+            let start = usize::from_str_radix(start, 16).unwrap();
+            let end = usize::from_str_radix(end, 16).unwrap();
+            if (start..end).contains(&address) {
+                return permissions.to_string();
+            }
+        }
+
+        panic!("No mapping contains {address:#x}");
+    }
+
+    /// Returns the `VmFlags` of the mapping containing `address`.
+    fn vm_flags(address: usize) -> String {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut in_mapping = false;
+
+        for line in smaps.lines() {
+            if let Some(flags) = line.strip_prefix("VmFlags:") {
+                if in_mapping {
+                    return flags.to_string();
+                }
+            } else if let Some((start, end)) = line
+                .split_once(' ')
+                .and_then(|(range, _)| range.split_once('-'))
+            {
+                let start = usize::from_str_radix(start, 16).unwrap_or(usize::MAX);
+                let end = usize::from_str_radix(end, 16).unwrap_or(0);
+                in_mapping = (start..end).contains(&address);
+            }
+        }
+
+        panic!("No mapping contains {address:#x}");
+    }
+
+    #[test]
+    fn wiped_on_fork() {
+        let guarded = GuardedBytes::from_slice(TEST_STRING.as_bytes());
+
+        let flags = vm_flags(guarded.data_ptr() as usize);
+        assert!(
+            flags.contains(" wf"),
+            "Not marked as MADV_WIPEONFORK: {flags}"
+        );
+    }
+
+    #[test]
+    fn protected_while_encrusted() {
+        let mut encrusted = Encrusted::new(GuardedString::from(TEST_STRING), 0x2357_bd11);
+        let address = encrusted.data.0.data_ptr() as usize;
+
+        assert!(encrusted.data.0.protected);
+        assert!(permissions(address).starts_with("---"));
+
+        {
+            let decrusted = encrusted.decrust();
+            assert_eq!(TEST_STRING, &**decrusted);
+            assert!(permissions(address).starts_with("rw-"));
+        }
+
+        assert!(permissions(address).starts_with("---"));
+    }
+
+    #[test]
+    fn guard_pages() {
+        let bytes = GuardedBytes::from(vec![1; 10]);
+        let start = bytes.data_pages_ptr() as usize;
+        let page_size = page_size();
+
+        assert!(permissions(start - 1).starts_with("---"));
+        assert!(permissions(start).starts_with("rw-"));
+        assert!(permissions(start + bytes.data_pages).starts_with("---"));
+
+        // The data is placed next to the trailing guard page.
+        assert_eq!(start + page_size, bytes.as_ptr() as usize + bytes.len());
+    }
+
+    #[test]
+    fn modify() {
+        let mut encrusted = Encrusted::new(GuardedBytes::from_slice(&[1, 2, 3]), 0x2357_bd11);
+        encrusted.decrust()[1] = 5;
+
+        assert_eq!(&[1, 5, 3], &encrusted.decrust()[..]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Locking and marking the memory pages holding obfuscated heap data on Linux.
+//!
+//! Pages are locked using `mlock` to keep them from being swapped to disk, and marked with
+//! `MADV_DONTDUMP` to exclude them from core dumps.
+//!
+//! The pages are not marked with `MADV_WIPEONFORK`. Allocations that are freed while the data is
+//! deobfuscated, for example when a vector grows, remain hardened until the data is obfuscated
+//! again, while the allocator may already have handed their pages out to other data that children
+//! created using `fork` need. The pages of `GuardedBytes` are owned by this crate, and are wiped on
+//! fork instead.
+//!
+//! Hardening is best effort, errors, for example when exceeding `RLIMIT_MEMLOCK`, are ignored.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use crate::Encrustable;
+
+/// Number of allocations using each hardened page, as several small allocations may share a page.
+/// Pages are only unlocked and unmarked when the last allocation using them is released.
+static PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Keeps track of the allocations hardened for a single `Encrusted` object.
+pub(crate) struct Hardened {
+    allocations: Vec<(usize, usize)>,
+}
+
+impl Hardened {
+    pub(crate) const fn new() -> Self {
+        Self {
+            allocations: Vec::new(),
+        }
+    }
+
+    /// Hardens the allocations currently used by `data`, releasing allocations that are no longer
+    /// used.
+    pub(crate) fn update(&mut self, data: &mut impl Encrustable) {
+        let mut allocations = Vec::new();
+        data.visit_allocations(&mut |ptr, size| allocations.push((ptr as usize, size)));
+
+        if allocations == self.allocations {
+            return;
+        }
+
+        // Harden the new allocations before releasing the old ones, so that pages used by both are
+        // not unlocked in between.
+        for &(address, size) in &allocations {
+            harden(address, size);
+        }
+        for &(address, size) in &self.allocations {
+            release(address, size);
+        }
+
+        self.allocations = allocations;
+    }
+
+    /// Releases all hardened allocations. Must be called before the allocations are freed.
+    pub(crate) fn clear(&mut self) {
+        for (address, size) in self.allocations.drain(..) {
+            release(address, size);
+        }
+    }
+}
+
+fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+
+    *PAGE_SIZE.get_or_init(|| {
+        // SAFETY:
+        // `sysconf` does not access any memory in this process.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+        usize::try_from(page_size).unwrap_or(4096)
+    })
+}
+
+/// Returns the pages touched by the allocation.
+fn pages(address: usize, size: usize) -> std::ops::Range<usize> {
+    let page_size = page_size();
+
+    address - address % page_size..(address + size).next_multiple_of(page_size)
+}
+
+fn harden(address: usize, size: usize) {
+    let page_size = page_size();
+    let touched = pages(address, size);
+    let mut pages = PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for page in touched.step_by(page_size) {
+        let count = pages.entry(page).or_insert(0);
+
+        if *count == 0 {
+            // SAFETY:
+            // Locking and marking pages does not modify their contents.
+            unsafe {
+                libc::mlock(page as *const libc::c_void, page_size);
+                libc::madvise(page as *mut libc::c_void, page_size, libc::MADV_DONTDUMP);
+            }
+        }
+
+        *count += 1;
+    }
+}
+
+fn release(address: usize, size: usize) {
+    let page_size = page_size();
+    let touched = pages(address, size);
+    let mut pages = PAGES.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for page in touched.step_by(page_size) {
+        let Some(count) = pages.get_mut(&page) else {
+            continue;
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            pages.remove(&page);
+
+            // SAFETY:
+            // Unlocking and unmarking pages does not modify their contents.
+            unsafe {
+                libc::munlock(page as *const libc::c_void, page_size);
+                libc::madvise(page as *mut libc::c_void, page_size, libc::MADV_DODUMP);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    /// Returns the `VmFlags` of the mapping containing `address`.
+    fn vm_flags(address: usize) -> String {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut in_mapping = false;
+
+        for line in smaps.lines() {
+            if let Some(flags) = line.strip_prefix("VmFlags:") {
+                if in_mapping {
+                    return flags.to_string();
+                }
+            } else if let Some((start, end)) = line
+                .split_once(' ')
+                .and_then(|(range, _)| range.split_once('-'))
+            {
+                let start = usize::from_str_radix(start, 16).unwrap_or(usize::MAX);
+                let end = usize::from_str_radix(end, 16).unwrap_or(0);
+                in_mapping = (start..end).contains(&address);
+            }
+        }
+
+        panic!("No mapping contains {address:#x}");
+    }
+
+    #[test]
+    fn hardens_allocations() {
+        let page_size = page_size();
+        let mut secret = String::with_capacity(4 * page_size);
+        secret.push_str("A secret string");
+
+        let encrusted = Encrusted::new(secret, 0x2357_bd11);
+        // The second page of the allocation is fully covered by it.
+        let page = (encrusted.data.as_ptr() as usize).next_multiple_of(page_size) + page_size;
+
+        assert_eq!(
+            Some(&1),
+            PAGES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&page)
+        );
+
+        let flags = vm_flags(page);
+        assert!(
+            flags.contains(" dd"),
+            "Not marked as MADV_DONTDUMP: {flags}"
+        );
+        assert!(!flags.contains(" wf"), "Marked as MADV_WIPEONFORK: {flags}");
+
+        drop(encrusted);
+
+        assert_eq!(
+            None,
+            PAGES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&page)
+        );
+    }
+
+    #[test]
+    fn follows_reallocations() {
+        let page_size = page_size();
+        let mut encrusted = Encrusted::new(vec![0u8; 16], 0x2357_bd11);
+        let old_allocation = encrusted.hardened.allocations.clone();
+
+        encrusted.decrust().resize(4 * page_size, 1);
+
+        assert_ne!(old_allocation, encrusted.hardened.allocations);
+        assert_eq!(
+            vec![(encrusted.data.as_ptr() as usize, encrusted.data.capacity())],
+            encrusted.hardened.allocations
+        );
+    }
+}
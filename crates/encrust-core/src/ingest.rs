@@ -0,0 +1,326 @@
+//! Reading secrets supplied at runtime directly into obfuscated storage.
+//!
+//! Secrets are read without intermediate buffers, and moved into the [`Encrusted`] object without
+//! being copied. Buffers that have to grow while reading are zeroized before they are freed.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use zeroize::Zeroize;
+
+use crate::{Backend, Bytes, Encrusted, growth::reserve_zeroizing};
+
+/// Number of bytes the buffer is grown by when the size of a file is not known.
+const READ_SIZE: usize = 4096;
+
+/// Reads the whole file at `path` into a buffer, zeroizing the buffer if it grows or if reading
+/// fails.
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file
+        .metadata()
+        .ok()
+        .and_then(|metadata| usize::try_from(metadata.len()).ok())
+        .unwrap_or(0);
+
+    // One byte is added to the known size so that reaching the end of the file does not grow the
+    // buffer.
+    let mut data = Vec::new();
+    reserve_zeroizing(&mut data, size.saturating_add(1));
+
+    loop {
+        if data.len() == data.capacity() {
+            reserve_zeroizing(&mut data, READ_SIZE);
+        }
+
+        let len = data.len();
+        data.resize(data.capacity(), 0);
+
+        match file.read(&mut data[len..]) {
+            Ok(0) => {
+                data.truncate(len);
+                return Ok(data);
+            }
+            Ok(read) => data.truncate(len + read),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => data.truncate(len),
+            Err(error) => {
+                data.zeroize();
+                return Err(error);
+            }
+        }
+    }
+}
+
+/// Reads the file at `path` as UTF-8, zeroizing the bytes if they are not valid UTF-8.
+fn read_file_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read_file(path)?).map_err(|error| {
+        error.into_bytes().zeroize();
+
+        io::Error::new(io::ErrorKind::InvalidData, "File is not valid UTF-8")
+    })
+}
+
+/// Converts the value of the environment variable `name` to a string, zeroizing the value if it is
+/// not valid UTF-8.
+fn env_value_to_string(name: &str, value: Option<OsString>) -> io::Result<String> {
+    let value = value.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Environment variable {name} is not set"),
+        )
+    })?;
+
+    value.into_string().map_err(|value: OsString| {
+        value.into_encoded_bytes().zeroize();
+
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Environment variable {name} is not valid UTF-8"),
+        )
+    })
+}
+
+/// Returns the path of the credential `name` in `directory`, the value of
+/// `$CREDENTIALS_DIRECTORY`.
+fn credential_path(directory: Option<OsString>, name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid credential name",
+        ));
+    }
+
+    let directory = directory.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "CREDENTIALS_DIRECTORY is not set")
+    })?;
+
+    Ok(Path::new(&directory).join(name))
+}
+
+impl<B> Encrusted<String, B>
+where
+    B: Backend,
+{
+    /// Reads the environment variable `name`, obfuscates it using a random key and removes it from
+    /// the environment of the process.
+    ///
+    /// The variable is removed using [`env::remove_var`], which does not overwrite the memory
+    /// holding the value, so a copy may remain in the memory of the process.
+    ///
+    /// # Safety
+    /// Removing the variable has the same safety requirements as [`env::remove_var`]: no other
+    /// thread may read or write the environment at the same time, including through functions in
+    /// the C library.
+    ///
+    /// # Errors
+    /// Returns an error of the kind [`io::ErrorKind::NotFound`] if the variable is not set, and an
+    /// error of the kind [`io::ErrorKind::InvalidData`] if it is not valid UTF-8. The variable is
+    /// removed in both cases.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// // SAFETY: No other threads access the environment in this example.
+    /// unsafe {
+    ///     std::env::set_var("DB_PASSWORD", "A secret password");
+    ///     let mut password = Encrusted::<String>::from_env("DB_PASSWORD").unwrap();
+    ///     assert_eq!("A secret password", password.decrust().as_str());
+    /// }
+    ///
+    /// assert!(std::env::var_os("DB_PASSWORD").is_none());
+    /// ```
+    pub unsafe fn from_env(name: &str) -> io::Result<Self> {
+        let value = env::var_os(name);
+
+        // SAFETY:
+        // The caller guarantees that the environment is not accessed concurrently.
+        unsafe {
+            env::remove_var(name);
+        }
+
+        Ok(Self::with_random_key(env_value_to_string(name, value)?))
+    }
+
+    /// Reads the file at `path` as UTF-8 and obfuscates it using a random key.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, and an error of the kind
+    /// [`io::ErrorKind::InvalidData`] if it is not valid UTF-8.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::with_random_key(read_file_to_string(path.as_ref())?))
+    }
+
+    /// Works like [`Encrusted::from_file`], but removes the file after it has been read.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or removed. The file is not removed if it cannot
+    /// be read.
+    pub fn take_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let encrusted = Self::from_file(path.as_ref())?;
+        std::fs::remove_file(path)?;
+
+        Ok(encrusted)
+    }
+
+    /// Reads the credential `name` from the directory given by `$CREDENTIALS_DIRECTORY`, as set
+    /// by systemd for services using `LoadCredential=` and similar options, and obfuscates it using
+    /// a random key.
+    ///
+    /// # Errors
+    /// Returns an error of the kind [`io::ErrorKind::NotFound`] if `$CREDENTIALS_DIRECTORY` is not
+    /// set, an error of the kind [`io::ErrorKind::InvalidInput`] if `name` is not a valid file
+    /// name, and the same errors as [`Encrusted::from_file`].
+    pub fn from_credential(name: &str) -> io::Result<Self> {
+        Self::from_file(credential_path(env::var_os("CREDENTIALS_DIRECTORY"), name)?)
+    }
+}
+
+impl<B> Encrusted<Bytes, B>
+where
+    B: Backend,
+{
+    /// Reads the file at `path` and obfuscates it using a random key.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = read_file(path.as_ref())?;
+
+        Ok(Self::with_random_key(Bytes::from(bytes)))
+    }
+
+    /// Works like [`Encrusted::from_file`], but removes the file after it has been read.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or removed. The file is not removed if it cannot
+    /// be read.
+    pub fn take_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let encrusted = Self::from_file(path.as_ref())?;
+        std::fs::remove_file(path)?;
+
+        Ok(encrusted)
+    }
+
+    /// Reads the credential `name` from the directory given by `$CREDENTIALS_DIRECTORY`, as set
+    /// by systemd for services using `LoadCredential=` and similar options, and obfuscates it using
+    /// a random key.
+    ///
+    /// # Errors
+    /// Returns an error of the kind [`io::ErrorKind::NotFound`] if `$CREDENTIALS_DIRECTORY` is not
+    /// set, an error of the kind [`io::ErrorKind::InvalidInput`] if `name` is not a valid file
+    /// name, and the same errors as [`Encrusted::from_file`].
+    pub fn from_credential(name: &str) -> io::Result<Self> {
+        Self::from_file(credential_path(env::var_os("CREDENTIALS_DIRECTORY"), name)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EncrustedBytes, SmallRngBackend};
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    /// Returns a path in the temporary directory unique to this test process.
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("encrust-{}-{name}", std::process::id()))
+    }
+
+    // The environment is not modified by the tests, as other tests may read it at the same time,
+    // for example when spawning processes. `from_env` is tested by its documentation example.
+    #[test]
+    fn env_values() {
+        assert_eq!(
+            TEST_STRING,
+            env_value_to_string("SECRET", Some(TEST_STRING.into())).unwrap()
+        );
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            env_value_to_string("SECRET", None).unwrap_err().kind()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+
+            let invalid = OsString::from_vec(vec![0xff, 0xfe]);
+            assert_eq!(
+                io::ErrorKind::InvalidData,
+                env_value_to_string("SECRET", Some(invalid))
+                    .unwrap_err()
+                    .kind()
+            );
+        }
+    }
+
+    #[test]
+    fn from_file() {
+        let path = temp_path("from_file");
+        let data = TEST_STRING.repeat(200);
+        std::fs::write(&path, &data).unwrap();
+
+        let mut string = Encrusted::<String>::from_file(&path).unwrap();
+        assert_eq!(data, *string.decrust());
+
+        let mut bytes = EncrustedBytes::<SmallRngBackend>::take_file(&path).unwrap();
+        assert_eq!(data.as_bytes(), bytes.decrust().as_slice());
+        assert!(!path.exists());
+
+        let missing = Encrusted::<String>::from_file(&path);
+        assert_eq!(
+            Some(io::ErrorKind::NotFound),
+            missing.err().map(|error| error.kind())
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let path = temp_path("invalid_utf8");
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+
+        let result = Encrusted::<String>::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Some(io::ErrorKind::InvalidData),
+            result.err().map(|error| error.kind())
+        );
+    }
+
+    #[test]
+    fn from_credential() {
+        let directory = temp_path("credentials");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("db_password"), TEST_STRING).unwrap();
+
+        let path =
+            credential_path(Some(directory.clone().into_os_string()), "db_password").unwrap();
+        let mut encrusted = Encrusted::<String>::from_file(path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[test]
+    fn credential_names() {
+        for name in ["", ".", "..", "../secret", "dir/secret"] {
+            assert_eq!(
+                io::ErrorKind::InvalidInput,
+                credential_path(Some("/run/credentials".into()), name)
+                    .unwrap_err()
+                    .kind()
+            );
+        }
+
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            credential_path(None, "db_password").unwrap_err().kind()
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Optional integrity checks for encrusted data.
+//!
+//! Authenticated [`Encrusted`](crate::Encrusted) objects store an HMAC-SHA256 tag calculated over
+//! the obfuscated data. The tag is verified by
+//! [`Encrusted::try_decrust`](crate::Encrusted::try_decrust), which refuses to deobfuscate data
+//! that has been modified while obfuscated.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fmt;
+
+use hmac::{Hmac, Mac, digest::KeyInit};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use crate::{Backend, Keystream};
+
+/// Error returned by [`Encrusted::try_decrust`](crate::Encrusted::try_decrust) when the integrity
+/// of the obfuscated data cannot be verified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// The `Encrusted` object was not created with integrity protection, so there is nothing to
+    /// verify the data against.
+    NotAuthenticated,
+    /// The obfuscated data does not match the stored tag, meaning that either the data or the tag
+    /// has been modified.
+    Mismatch,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAuthenticated => write!(f, "encrusted data is not authenticated"),
+            Self::Mismatch => write!(f, "encrusted data does not match its integrity tag"),
+        }
+    }
+}
+
+impl core::error::Error for IntegrityError {}
+
+/// The MAC used to authenticate obfuscated data. Data is added to the MAC by implementations of
+/// [`Authenticate`].
+pub struct IntegrityMac(Hmac<Sha256>);
+
+impl IntegrityMac {
+    /// Creates a new MAC for data obfuscated using `key`. The MAC key is taken from the start of
+    /// the MAC keystream generated by the backend `B`, see [`Backend::mac_keystream`].
+    #[doc(hidden)]
+    pub fn new<B>(key: &B::Key) -> Self
+    where
+        B: Backend,
+    {
+        Self::from_keystream(B::mac_keystream(key))
+    }
+
+    /// Creates a new MAC whose key is taken from the start of `keystream`.
+    pub(crate) fn from_keystream(mut keystream: impl Keystream) -> Self {
+        // HMAC-SHA256 uses 64 byte keys internally, shorter keys are padded with zeros.
+        let mut mac_key = [0u8; 64];
+        keystream.fill(&mut mac_key);
+
+        let mac = <Hmac<Sha256> as KeyInit>::new(&mac_key.into());
+        mac_key.zeroize();
+
+        Self(mac)
+    }
+
+    /// Adds `bytes` to the MAC.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Returns the final tag. Used by the macros to include pre-calculated tags and should not be
+    /// used outside of the provided macros.
+    #[doc(hidden)]
+    pub fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into_bytes().into()
+    }
+
+    /// Verifies the MAC against `tag` in constant time.
+    pub(crate) fn verify(self, tag: &[u8; 32]) -> bool {
+        self.0.verify_slice(tag).is_ok()
+    }
+}
+
+/// Trait required to use data types with integrity protection. It feeds a platform independent
+/// representation of the obfuscated data into an [`IntegrityMac`].
+///
+/// If it is avoidable, do not implement this manually, but use the derive macro to generate the
+/// implementation. Every part of the data modified by [`Encrustable::toggle_encrust`] should be
+/// added to the MAC.
+///
+/// [`Encrustable::toggle_encrust`]: crate::Encrustable::toggle_encrust
+pub trait Authenticate {
+    /// Adds the data in `self` to `mac`.
+    fn authenticate(&self, mac: &mut IntegrityMac);
+}
+
+macro_rules! authenticate_number {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl Authenticate for $t {
+                fn authenticate(&self, mac: &mut IntegrityMac) {
+                    mac.update(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+authenticate_number!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128);
+
+// `usize` and `isize` are authenticated as 64-bit numbers so tags calculated by the macros do not
+// depend on the pointer width of the compiling machine.
+impl Authenticate for usize {
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        mac.update(&(*self as u64).to_le_bytes());
+    }
+}
+
+impl Authenticate for isize {
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        mac.update(&(*self as i64).to_le_bytes());
+    }
+}
+
+impl Authenticate for String {
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        mac.update(&(self.len() as u64).to_le_bytes());
+        mac.update(self.as_bytes());
+    }
+}
+
+impl<T, const N: usize> Authenticate for [T; N]
+where
+    T: Authenticate,
+{
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        for element in self {
+            element.authenticate(mac);
+        }
+    }
+}
+
+impl<T> Authenticate for Vec<T>
+where
+    T: Authenticate,
+{
+    fn authenticate(&self, mac: &mut IntegrityMac) {
+        mac.update(&(self.len() as u64).to_le_bytes());
+
+        for element in self {
+            element.authenticate(mac);
+        }
+    }
+}
+
+/// Tag stored by authenticated `Encrusted` objects, together with the function used to calculate
+/// it. Storing the function makes it possible to update the tag without requiring `T:
+/// Authenticate` everywhere the data is re-obfuscated.
+pub(crate) struct Integrity<T, B>
+where
+    B: Backend,
+{
+    tag: [u8; 32],
+    mac: fn(&T, &B::Key) -> IntegrityMac,
+}
+
+impl<T, B> Integrity<T, B>
+where
+    T: Authenticate,
+    B: Backend,
+{
+    pub(crate) fn new(data: &T, key: &B::Key) -> Self {
+        let mac_fn = mac::<T, B>;
+
+        Self {
+            tag: mac_fn(data, key).finalize(),
+            mac: mac_fn,
+        }
+    }
+
+    pub(crate) const fn from_tag(tag: [u8; 32]) -> Self {
+        Self {
+            tag,
+            mac: mac::<T, B>,
+        }
+    }
+}
+
+impl<T, B> Integrity<T, B>
+where
+    B: Backend,
+{
+    pub(crate) fn tag(&self) -> &[u8; 32] {
+        &self.tag
+    }
+
+    pub(crate) fn verify(&self, data: &T, key: &B::Key) -> bool {
+        (self.mac)(data, key).verify(&self.tag)
+    }
+
+    pub(crate) fn update(&mut self, data: &T, key: &B::Key) {
+        self.tag.zeroize();
+        self.tag = (self.mac)(data, key).finalize();
+    }
+}
+
+fn mac<T, B>(data: &T, key: &B::Key) -> IntegrityMac
+where
+    T: Authenticate,
+    B: Backend,
+{
+    let mut mac = IntegrityMac::new::<B>(key);
+    data.authenticate(&mut mac);
+
+    mac
+}
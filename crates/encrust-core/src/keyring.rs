@@ -0,0 +1,238 @@
+//! Keys stored in the Linux kernel keyring.
+
+use std::{
+    ffi::CString,
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use zeroize::Zeroize;
+
+use crate::{Backend, SeekableBackend, SmallRngBackend, provider::derive_key};
+
+// Constants from `linux/keyctl.h`.
+const KEY_SPEC_PROCESS_KEYRING: libc::c_long = -2;
+const KEYCTL_READ: libc::c_long = 11;
+const KEYCTL_INVALIDATE: libc::c_long = 21;
+
+/// Used to give each key added to the keyring a unique description, as adding a key with the same
+/// description as an existing key replaces it.
+static KEY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Backend storing the key material in the process keyring of the Linux kernel, only reading it
+/// while the data is obfuscated or deobfuscated. The key material is used to generate a key for the
+/// backend `B`.
+///
+/// The only thing stored next to the obfuscated data is the serial number of the key in the
+/// keyring, so the data cannot be deobfuscated using a memory dump of the process alone. Each
+/// `Encrusted` object gets its own key in the keyring, which is removed when the `Encrusted` object
+/// is dropped or its key is replaced.
+///
+/// Keys are added to the keyring by [`Backend::key_from_material`], so use
+/// [`Encrusted::with_random_key`] to create `Encrusted` objects using this backend. Child processes
+/// created using `fork` do not share the process keyring of their parent, and will not be able to
+/// deobfuscate data created by their parent.
+///
+/// # Panics
+/// Obfuscating and deobfuscating data panics if the key cannot be added to or read from the
+/// keyring, for example if the keyring syscalls are blocked.
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, KeyringBackend};
+///
+/// let mut api_token =
+///     Encrusted::<_, KeyringBackend>::with_random_key("A secret token".to_string());
+/// assert_eq!("A secret token", api_token.decrust().as_str());
+/// ```
+///
+/// [`Encrusted::with_random_key`]: crate::Encrusted::with_random_key
+pub struct KeyringBackend<B = SmallRngBackend>(PhantomData<B>);
+
+impl<B> Backend for KeyringBackend<B>
+where
+    B: Backend,
+{
+    type Key = KeyringKey;
+    type Keystream = B::Keystream;
+
+    const VERSION: u8 = B::VERSION;
+    const KEY_SIZE: usize = 32;
+
+    fn keystream(key: &Self::Key) -> Self::Keystream {
+        let mut backend_key = Self::backend_key(key);
+        let keystream = B::keystream(&backend_key);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn versioned_keystream(key: &Self::Key, version: u8) -> Option<Self::Keystream> {
+        let mut backend_key = Self::backend_key(key);
+        let keystream = B::versioned_keystream(&backend_key, version);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+        let mut backend_key = Self::backend_key(key);
+        let keystream = B::mac_keystream(&backend_key);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn key_from_material(material: &[u8]) -> Self::Key {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(material);
+
+        let key = KeyringKey::add(&bytes);
+        bytes.zeroize();
+
+        key
+    }
+}
+
+impl<B> KeyringBackend<B>
+where
+    B: Backend,
+{
+    /// Derives the key of `B` from the key material stored in the keyring.
+    fn backend_key(key: &KeyringKey) -> B::Key {
+        let mut material = [0; 32];
+        key.read(&mut material);
+
+        let backend_key = derive_key::<B>(&material, b"encrust:keyring", 0);
+        material.zeroize();
+
+        backend_key
+    }
+}
+
+impl<B> SeekableBackend for KeyringBackend<B>
+where
+    B: SeekableBackend,
+{
+    fn seek(keystream: &mut Self::Keystream, block: u64) {
+        B::seek(keystream, block);
+    }
+}
+
+/// Serial number of a key stored in the kernel keyring by [`KeyringBackend`].
+///
+/// Zeroizing a `KeyringKey` invalidates the key in the keyring, removing it.
+pub struct KeyringKey(i32);
+
+impl KeyringKey {
+    fn add(material: &[u8; 32]) -> Self {
+        let description = CString::new(format!(
+            "encrust:{}:{}",
+            std::process::id(),
+            KEY_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))
+        .expect("The key description does not contain null bytes");
+
+        // SAFETY:
+        // All pointers are valid null-terminated strings or point to a buffer of the given length.
+        let serial = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                c"user".as_ptr(),
+                description.as_ptr(),
+                material.as_ptr(),
+                material.len(),
+                KEY_SPEC_PROCESS_KEYRING,
+            )
+        };
+
+        assert!(
+            serial > 0,
+            "Unable to add key to the kernel keyring: {}",
+            std::io::Error::last_os_error()
+        );
+
+        Self(i32::try_from(serial).expect("Key serial numbers are 32-bit"))
+    }
+
+    fn read(&self, material: &mut [u8; 32]) {
+        // SAFETY:
+        // `material` is a valid buffer of the given length, the kernel never writes more than the
+        // given length to it.
+        let length = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                libc::c_long::from(self.0),
+                material.as_mut_ptr(),
+                material.len(),
+            )
+        };
+
+        assert!(
+            length == 32,
+            "Unable to read key from the kernel keyring: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+impl Zeroize for KeyringKey {
+    fn zeroize(&mut self) {
+        if self.0 != 0 {
+            // SAFETY:
+            // Invalidating a key does not access any memory in this process. Errors are ignored, as
+            // the key is not used again either way.
+            unsafe {
+                libc::syscall(
+                    libc::SYS_keyctl,
+                    KEYCTL_INVALIDATE,
+                    libc::c_long::from(self.0),
+                );
+            }
+
+            self.0 = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog";
+
+    #[test]
+    fn keyring_backend() {
+        let mut encrusted =
+            Encrusted::<_, KeyringBackend>::with_random_key(TEST_STRING.to_string());
+        assert_ne!(TEST_STRING.as_bytes(), encrusted.data.as_bytes());
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[test]
+    fn zeroize_removes_key() {
+        let mut key = KeyringBackend::<SmallRngBackend>::key_from_material(&[0x42; 32]);
+        let serial = key.0;
+
+        let mut material = [0; 32];
+        key.read(&mut material);
+
+        key.zeroize();
+        assert_eq!(0, key.0);
+
+        // SAFETY:
+        // See `KeyringKey::read`.
+        let length = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                libc::c_long::from(serial),
+                material.as_mut_ptr(),
+                material.len(),
+            )
+        };
+        assert_eq!(-1, length);
+    }
+}
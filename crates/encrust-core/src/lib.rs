@@ -3,10 +3,79 @@
 
 //! Crate implementing core functionality for `encrust`. See the main crate for documentation.
 
+mod backend;
+mod builder;
+mod bytes;
+mod concealed;
+mod encode;
+mod growth;
+#[cfg(all(feature = "guarded", target_os = "linux"))]
+mod guarded;
+#[cfg(all(feature = "harden", target_os = "linux"))]
+mod harden;
 #[cfg(feature = "hashstrings")]
 mod hashstrings;
+#[cfg(feature = "std")]
+mod ingest;
+#[cfg(feature = "integrity")]
+mod integrity;
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+mod keyring;
+#[cfg(feature = "std")]
+mod lock;
+#[cfg(all(feature = "serde", feature = "std"))]
+mod plaintext;
+#[cfg(feature = "std")]
+mod process;
+#[cfg(all(feature = "prompt", unix))]
+mod prompt;
+mod provider;
+#[cfg(feature = "std")]
+mod reader;
+mod sealed;
+#[cfg(feature = "serde")]
+mod serialize;
+mod visitor;
+#[cfg(feature = "aes")]
+pub use backend::{AesCtrBackend, AesCtrKeystream};
+pub use backend::{
+    Backend, Keystream, SealableBackend, SeekableBackend, SmallRngBackend, SmallRngKeystream,
+};
+#[cfg(feature = "chacha20")]
+pub use backend::{ChaCha20Backend, ChaCha20Keystream};
+pub use builder::{EncrustedBytesBuilder, EncrustedStringBuilder};
+pub use bytes::{Bytes, DecrustedRange, EncrustedBytes};
+pub use concealed::{ConcealedBytes, ConcealedString};
+pub use encode::EncodeObfuscated;
+#[cfg(all(feature = "guarded", target_os = "linux"))]
+pub use guarded::{GuardedBytes, GuardedString};
+#[cfg(all(feature = "harden", target_os = "linux"))]
+use harden::Hardened;
 #[cfg(feature = "hashstrings")]
 pub use hashstrings::*;
+#[cfg(feature = "integrity")]
+use integrity::Integrity;
+#[cfg(feature = "integrity")]
+pub use integrity::{Authenticate, IntegrityError, IntegrityMac};
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+pub use keyring::{KeyringBackend, KeyringKey};
+#[cfg(feature = "std")]
+pub use lock::{EncrustedLock, EncrustedReadGuard, EncrustedWriteGuard};
+#[cfg(all(feature = "serde", feature = "std"))]
+pub use plaintext::{DeserializePlaintext, PlaintextSeed, deserialize_plaintext};
+#[cfg(feature = "std")]
+pub use process::{EncrustedCommandExt, WriteDecrusted};
+#[cfg(all(feature = "prompt", unix))]
+pub use prompt::{prompt_password, prompt_password_with_backend};
+pub use provider::{KeyProvider, Provided, combine_key};
+#[cfg(feature = "std")]
+pub use provider::{key_from_env, key_from_file};
+#[cfg(feature = "std")]
+pub use reader::EncrustedReader;
+pub use sealed::{SEALED_FORMAT_VERSION, SEALED_MAGIC, SealedError};
+#[cfg(feature = "serde")]
+pub use serialize::SERDE_FORMAT_VERSION;
+pub use visitor::{EncrustField, EncrustVisitor, VisitEncrust};
 
 #[cfg(not(feature = "std"))]
 extern crate core;
@@ -17,33 +86,105 @@ extern crate alloc;
 #[cfg(not(feature = "std"))]
 use alloc::{string::String, vec::Vec};
 #[cfg(not(feature = "std"))]
-use core::ops::{Deref, DerefMut};
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
 #[cfg(feature = "std")]
-use std::ops::{Deref, DerefMut};
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
 
-use rand::{RngCore, SeedableRng, rngs::SmallRng};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 
 /// Container struct for encrust, accepting [`Encrustable`] + `Zeroize` types for obfuscation and
 /// deobfuscation when needed.
 ///
+/// The keystream used for obfuscation is generated by the [`Backend`] `B`, which defaults to
+/// [`SmallRngBackend`]. Use [`Encrusted::with_key`] to create an `Encrusted` object with a
+/// different backend.
+///
+/// With the `integrity` feature enabled, `Encrusted` objects created with
+/// [`Encrusted::new_authenticated`] or [`Encrusted::with_key_authenticated`] also store a MAC of
+/// the obfuscated data, which is checked by [`Encrusted::try_decrust`].
+///
 /// Care should be taken if `T` has a non-trivial `Drop` implementation, as `T` is not dropped until
 /// `zeroize` has been called on it.
-pub struct Encrusted<T>
+pub struct Encrusted<T, B = SmallRngBackend>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
     data: T,
-    seed: u64,
+    key: B::Key,
+    #[cfg(feature = "integrity")]
+    integrity: Option<Integrity<T, B>>,
+    #[cfg(feature = "std")]
+    rotate_key: bool,
+    #[cfg(all(feature = "harden", target_os = "linux"))]
+    hardened: Hardened,
+    backend: PhantomData<B>,
 }
 
 impl<T> Encrusted<T>
 where
     T: Encrustable + Zeroize,
 {
-    /// Accepts [`Encrustable`] + `Zeroize` data and obfuscates it using the provided seed.
-    pub fn new(mut data: T, seed: u64) -> Self {
-        let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    /// Accepts [`Encrustable`] + `Zeroize` data and obfuscates it using the provided seed and the
+    /// default [`SmallRngBackend`].
+    pub fn new(data: T, seed: u64) -> Self {
+        Self::with_key(data, seed)
+    }
+
+    /// Works like [`Encrusted::new`], but uses a random seed from the thread-local random number
+    /// generator.
+    #[cfg(feature = "std")]
+    pub fn new_random(data: T) -> Self {
+        Self::with_random_key(data)
+    }
+
+    /// Works like [`Encrusted::new`], but additionally stores a MAC of the obfuscated data that is
+    /// verified by [`Encrusted::try_decrust`].
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let mut license_key = Encrusted::new_authenticated("ABCD-1234".to_string(), 0xc0ffee);
+    /// assert_eq!("ABCD-1234", license_key.try_decrust().unwrap().as_str());
+    /// ```
+    #[cfg(feature = "integrity")]
+    pub fn new_authenticated(data: T, seed: u64) -> Self
+    where
+        T: Authenticate,
+    {
+        Self::with_key_authenticated(data, seed)
+    }
+}
+
+impl<T, B> Encrusted<T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    /// Accepts [`Encrustable`] + `Zeroize` data and obfuscates it using the provided key and the
+    /// backend `B`.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::{Encrusted, SmallRngBackend};
+    ///
+    /// let mut encrusted = Encrusted::<_, SmallRngBackend>::with_key(1337u32, 0xc0ffee);
+    /// assert_eq!(1337, *encrusted.decrust());
+    /// ```
+    pub fn with_key(mut data: T, key: B::Key) -> Self {
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        let mut hardened = Hardened::new();
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        hardened.update(&mut data);
+
+        let mut encrust_rng = B::keystream(&key);
 
         // SAFETY:
         // `Encrusted` takes ownership of the data and only exposes it after calling toggle_encrust
@@ -52,114 +193,410 @@ where
             data.toggle_encrust(&mut encrust_rng);
         }
 
-        Self { data, seed }
+        Self {
+            data,
+            key,
+            #[cfg(feature = "integrity")]
+            integrity: None,
+            #[cfg(feature = "std")]
+            rotate_key: false,
+            #[cfg(all(feature = "harden", target_os = "linux"))]
+            hardened,
+            backend: PhantomData,
+        }
+    }
+
+    /// Works like [`Encrusted::with_key`], but uses a random key generated using the thread-local
+    /// random number generator.
+    #[cfg(feature = "std")]
+    pub fn with_random_key(data: T) -> Self {
+        Self::with_key(data, backend::random_key::<B>())
+    }
+
+    /// Works like [`Encrusted::with_key`], but additionally stores a MAC of the obfuscated data
+    /// that is verified by [`Encrusted::try_decrust`].
+    #[cfg(feature = "integrity")]
+    pub fn with_key_authenticated(data: T, key: B::Key) -> Self
+    where
+        T: Authenticate,
+    {
+        let mut encrusted = Self::with_key(data, key);
+        encrusted.integrity = Some(Integrity::new(&encrusted.data, &encrusted.key));
+
+        encrusted
     }
 
     /// Creates an `Encrusted` object from pre-scrambeled data. This is used by macros to include
     /// pre-scrambled objects in the source and should not be called manually.
     ///
+    /// `version` is the version of the backend's algorithm used by the macros. Data obfuscated
+    /// using an older version is obfuscated again using the current version.
+    ///
     /// # Safety
     /// Using this may cause data to be scrambled in unpredictable ways that could lead to safety
     /// issues. This should not be used manually, but only through the provided macros.
+    ///
+    /// # Panics
+    /// Panics if the backend does not support `version`.
     #[doc(hidden)]
     #[cfg(feature = "macros")]
-    pub const unsafe fn from_encrusted_data(data: T, seed: u64) -> Self {
-        Self { data, seed }
+    pub unsafe fn from_encrusted_data(data: T, key: B::Key, version: u8) -> Self {
+        Self::from_versioned(data, key, version).expect("Unsupported algorithm version")
+    }
+
+    /// Creates an `Encrusted` object from data that is already obfuscated using `key`.
+    const fn from_obfuscated(data: T, key: B::Key) -> Self {
+        Self {
+            data,
+            key,
+            #[cfg(feature = "integrity")]
+            integrity: None,
+            #[cfg(feature = "std")]
+            rotate_key: false,
+            #[cfg(all(feature = "harden", target_os = "linux"))]
+            hardened: Hardened::new(),
+            backend: PhantomData,
+        }
+    }
+
+    /// Creates an `Encrusted` object from data obfuscated using `key` and version `version` of the
+    /// backend's algorithm. Data obfuscated using an older version is deobfuscated using that
+    /// version and obfuscated again using the current version.
+    ///
+    /// Returns `None` if the backend does not support `version`, in which case `key` and `data`
+    /// are zeroized.
+    pub(crate) fn from_versioned(mut data: T, mut key: B::Key, version: u8) -> Option<Self> {
+        if version == B::VERSION {
+            return Some(Self::from_obfuscated(data, key));
+        }
+
+        let Some(mut decruster) = B::versioned_keystream(&key, version) else {
+            key.zeroize();
+
+            // Types hiding their heap pointers cannot be zeroized or freed while obfuscated, so
+            // they are leaked instead.
+            if T::DECRUST_BEFORE_DROP {
+                core::mem::forget(data);
+            } else {
+                data.zeroize();
+            }
+
+            return None;
+        };
+
+        // SAFETY:
+        // The data is deobfuscated using the keystream it was obfuscated with, and is obfuscated
+        // again by `with_key` before it is accessible.
+        unsafe {
+            data.toggle_encrust(&mut decruster);
+        }
+
+        Some(Self::with_key(data, key))
+    }
+
+    /// Works like [`Encrusted::from_versioned`], but also stores `tag` as the MAC of the data.
+    ///
+    /// Tags are calculated over the obfuscated data, so data obfuscated using an older version no
+    /// longer matches its tag once it has been obfuscated again, and [`Encrusted::try_decrust`]
+    /// reports a mismatch.
+    #[cfg(feature = "integrity")]
+    pub(crate) fn from_versioned_authenticated(
+        data: T,
+        key: B::Key,
+        tag: [u8; 32],
+        version: u8,
+    ) -> Option<Self>
+    where
+        T: Authenticate,
+    {
+        let mut encrusted = Self::from_versioned(data, key, version)?;
+        encrusted.integrity = Some(Integrity::from_tag(tag));
+
+        Some(encrusted)
+    }
+
+    /// Creates an authenticated `Encrusted` object from pre-scrambled data and a pre-calculated
+    /// tag. This is used by macros and should not be called manually.
+    ///
+    /// # Safety
+    /// Using this may cause data to be scrambled in unpredictable ways that could lead to safety
+    /// issues. This should not be used manually, but only through the provided macros.
+    ///
+    /// # Panics
+    /// Panics if the backend does not support `version`.
+    #[doc(hidden)]
+    #[cfg(all(feature = "macros", feature = "integrity"))]
+    pub unsafe fn from_authenticated_encrusted_data(
+        data: T,
+        key: B::Key,
+        tag: [u8; 32],
+        version: u8,
+    ) -> Self
+    where
+        T: Authenticate,
+    {
+        Self::from_versioned_authenticated(data, key, tag, version)
+            .expect("Unsupported algorithm version")
     }
 
-    /// Changes the seed used to obfuscate the underlying data.
-    pub fn reseed(&mut self, new_seed: u64) {
+    /// Changes the key used to obfuscate the underlying data.
+    pub fn reseed(&mut self, new_key: B::Key) {
         {
-            let mut decruster = SmallRng::seed_from_u64(self.seed);
+            let mut decruster = B::keystream(&self.key);
 
             // SAFETY:
-            // In order to obfuscate with a new seed, the data needs to be deobfuscated first.
+            // In order to obfuscate with a new key, the data needs to be deobfuscated first.
             unsafe {
                 self.data.toggle_encrust(&mut decruster);
             }
         }
 
-        self.seed = new_seed;
+        self.key.zeroize();
+        self.key = new_key;
 
-        let mut encrust_rng = SmallRng::seed_from_u64(self.seed);
+        let mut encrust_rng = B::keystream(&self.key);
 
         // SAFETY:
-        // Obsucate the data again with a new seed.
+        // Obsucate the data again with a new key.
         unsafe {
             self.data.toggle_encrust(&mut encrust_rng);
         }
+
+        #[cfg(feature = "integrity")]
+        if let Some(integrity) = &mut self.integrity {
+            integrity.update(&self.data, &self.key);
+        }
+    }
+
+    /// Changes the key used to obfuscate the underlying data to a random key generated using the
+    /// thread-local random number generator.
+    #[cfg(feature = "std")]
+    pub fn reseed_random(&mut self) {
+        self.reseed(backend::random_key::<B>());
+    }
+
+    /// Enables or disables automatic key rotation. When enabled, the data is obfuscated using a new
+    /// random key every time a [`Decrusted`] object is dropped, so the obfuscated data changes
+    /// after every access even if the data itself is unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let mut encrusted = Encrusted::new_random(1337u64);
+    /// encrusted.set_key_rotation(true);
+    ///
+    /// // The data is obfuscated using a new key when `decrusted` is dropped.
+    /// let decrusted = encrusted.decrust();
+    /// assert_eq!(1337, *decrusted);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn set_key_rotation(&mut self, enabled: bool) {
+        self.rotate_key = enabled;
+    }
+
+    /// Returns `true` if automatic key rotation is enabled, see [`Encrusted::set_key_rotation`].
+    #[cfg(feature = "std")]
+    pub fn key_rotation(&self) -> bool {
+        self.rotate_key
+    }
+
+    /// Returns `true` if the `Encrusted` object stores a MAC of the obfuscated data.
+    #[cfg(feature = "integrity")]
+    pub fn is_authenticated(&self) -> bool {
+        self.integrity.is_some()
+    }
+
+    /// Verifies the integrity of the obfuscated data before deobfuscating it. Returns a
+    /// [`Decrusted`] object if the data has not been modified, otherwise an [`IntegrityError`] is
+    /// returned and the data is left obfuscated.
+    ///
+    /// This requires the `Encrusted` object to be created using [`Encrusted::new_authenticated`],
+    /// [`Encrusted::with_key_authenticated`] or by a macro using the `authenticated` option.
+    ///
+    /// # Errors
+    /// Returns [`IntegrityError::NotAuthenticated`] if the `Encrusted` object does not store a
+    /// MAC, and [`IntegrityError::Mismatch`] if the data does not match the stored MAC.
+    #[cfg(feature = "integrity")]
+    pub fn try_decrust(&mut self) -> Result<Decrusted<'_, T, B>, IntegrityError> {
+        match &self.integrity {
+            None => Err(IntegrityError::NotAuthenticated),
+            Some(integrity) if !integrity.verify(&self.data, &self.key) => {
+                Err(IntegrityError::Mismatch)
+            }
+            Some(_) => Ok(Decrusted::new(self)),
+        }
     }
 
     /// Deobfuscates the data contained in [`Encrusted`] and returns a [`Decrusted`] object that can
     /// be used to access and modify the actual data.
-    pub fn decrust(&mut self) -> Decrusted<'_, T> {
+    ///
+    /// The integrity of authenticated data is not verified, use `try_decrust` for that.
+    pub fn decrust(&mut self) -> Decrusted<'_, T, B> {
         Decrusted::new(self)
     }
+
+    /// Returns a deobfuscated copy of the data, leaving the stored data obfuscated. Unlike
+    /// [`Encrusted::decrust`], this only requires a shared reference. The copy is zeroized when it
+    /// is dropped.
+    ///
+    /// The copy is created by cloning the obfuscated data before deobfuscating it, so `T`'s `Clone`
+    /// implementation must not depend on the data being valid, which holds for derived `Clone`
+    /// implementations. The integrity of authenticated data is not verified.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let api_token = Encrusted::new("A secret token".to_string(), 0xc0ffee);
+    /// assert_eq!("A secret token", api_token.decrust_copy().as_str());
+    /// ```
+    pub fn decrust_copy(&self) -> Zeroizing<T>
+    where
+        T: Clone,
+    {
+        let mut copy = Zeroizing::new(self.data.clone());
+        let mut decruster = B::keystream(&self.key);
+
+        // SAFETY:
+        // The copy is deobfuscated using the same keystream as the stored data, and is not
+        // accessible until it has been deobfuscated.
+        unsafe {
+            copy.toggle_encrust(&mut decruster);
+        }
+
+        copy
+    }
+
+    /// Calls `f` with a reference to a deobfuscated copy of the data and returns the result. The
+    /// copy is zeroized when `f` returns. See [`Encrusted::decrust_copy`] for details.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let api_token = Encrusted::new("A secret token".to_string(), 0xc0ffee);
+    /// assert_eq!(14, api_token.peek(|token| token.len()));
+    /// ```
+    pub fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> R
+    where
+        T: Clone,
+    {
+        f(&self.decrust_copy())
+    }
+
+    /// Deobfuscates the data in place. Every call must be followed by a call to
+    /// [`Encrusted::conceal`] before the data is considered obfuscated again.
+    pub(crate) fn expose(&mut self) {
+        let mut decruster = B::keystream(&self.key);
+
+        // SAFETY:
+        // This needs to happen to deobfuscate the data for use. Without this, invalid data can
+        // cause problems, such as strings with data that is not valid UTF-8.
+        unsafe {
+            self.data.toggle_encrust(&mut decruster);
+        }
+
+        // Allocations are visited while the data is deobfuscated, as some types hide their
+        // allocations while obfuscated. Objects created by the macros are not hardened until they
+        // are first used.
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        self.hardened.update(&mut self.data);
+    }
+
+    /// Re-obfuscates data deobfuscated by [`Encrusted::expose`], using a new key if key rotation
+    /// is enabled, and updates the MAC of authenticated data.
+    pub(crate) fn conceal(&mut self) {
+        #[cfg(feature = "std")]
+        if self.rotate_key {
+            self.key.zeroize();
+            self.key = backend::random_key::<B>();
+        }
+
+        // The data may have been moved to new allocations while it was deobfuscated.
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        self.hardened.update(&mut self.data);
+
+        let mut encrust_rng = B::keystream(&self.key);
+
+        // SAFETY:
+        // This needs to happen to obfuscate the data when it is no longer used to ensure that data
+        // does not linger in memory unobfuscated when not needed. Data will not be accessible
+        // without deobfuscating the data, so this should not cause any issues.
+        unsafe {
+            self.data.toggle_encrust(&mut encrust_rng);
+        }
+
+        #[cfg(feature = "integrity")]
+        if let Some(integrity) = &mut self.integrity {
+            integrity.update(&self.data, &self.key);
+        }
+    }
 }
 
-impl<T> Drop for Encrusted<T>
+impl<T, B> Drop for Encrusted<T, B>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
-    /// [`Encrusted`]'s drop implementation calls zeroize on the underlying data including the seed
+    /// [`Encrusted`]'s drop implementation calls zeroize on the underlying data including the key
     /// to prevent secrets from staying in memory when they are no longer needed.
     ///
     /// Note that the data is zeroized prior to being dropped, which may cause problems for the drop
     /// implementation of the underlying data.
     fn drop(&mut self) {
+        // Types hiding their heap pointers cannot be zeroized or freed while obfuscated.
+        if T::DECRUST_BEFORE_DROP {
+            self.expose();
+        }
+
         self.data.zeroize();
-        self.seed.zeroize();
+        self.key.zeroize();
+
+        #[cfg(all(feature = "harden", target_os = "linux"))]
+        self.hardened.clear();
     }
 }
 
 /// Type used to access encrusted data. Use [`Encrusted::decrust`] to create `Decrusted` data.
 ///
-/// When the `Decrusted` object is dropped, the underlying data is re-obfuscated.
-pub struct Decrusted<'decrusted, T>
+/// When the `Decrusted` object is dropped, the underlying data is re-obfuscated, using a new key if
+/// key rotation is enabled. If the `Encrusted` object is authenticated, its MAC is updated to match
+/// any modifications.
+pub struct Decrusted<'decrusted, T, B = SmallRngBackend>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
-    encrusted_data: &'decrusted mut Encrusted<T>,
+    encrusted_data: &'decrusted mut Encrusted<T, B>,
 }
 
-impl<'decrusted, T> Decrusted<'decrusted, T>
+impl<'decrusted, T, B> Decrusted<'decrusted, T, B>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
-    fn new(encrusted_data: &'decrusted mut Encrusted<T>) -> Self {
-        let mut decruster = SmallRng::seed_from_u64(encrusted_data.seed);
-
-        // SAFETY:
-        // This needs to happen to deobfuscate the data for use. Without this, invalid data can
-        // cause problems, such as strings with data that is not valid UTF-8.
-        unsafe {
-            encrusted_data.data.toggle_encrust(&mut decruster);
-        }
+    fn new(encrusted_data: &'decrusted mut Encrusted<T, B>) -> Self {
+        encrusted_data.expose();
 
         Self { encrusted_data }
     }
 }
 
-impl<T> Drop for Decrusted<'_, T>
+impl<T, B> Drop for Decrusted<'_, T, B>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
     fn drop(&mut self) {
-        let mut encrust_rng = SmallRng::seed_from_u64(self.encrusted_data.seed);
-
-        // SAFETY:
-        // This needs to happen to obfuscate the data when this object is dropped to ensure that
-        // data does not linger in memory unobfuscated when not needed. Data will not be accessible
-        // without deobfuscating the data, so this should not cause any issues.
-        unsafe {
-            self.encrusted_data.data.toggle_encrust(&mut encrust_rng);
-        }
+        self.encrusted_data.conceal();
     }
 }
 
-impl<T> Deref for Decrusted<'_, T>
+impl<T, B> Deref for Decrusted<'_, T, B>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
     type Target = T;
 
@@ -168,9 +605,10 @@ where
     }
 }
 
-impl<T> DerefMut for Decrusted<'_, T>
+impl<T, B> DerefMut for Decrusted<'_, T, B>
 where
     T: Encrustable + Zeroize,
+    B: Backend,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.encrusted_data.data
@@ -178,7 +616,8 @@ where
 }
 
 /// Trait required to use data types with encrust. If it is avoidable, do not implement this
-/// manually, but use the derive macro to generate the implementation.
+/// manually, but use the derive macro to generate the implementation, or implement the safe
+/// [`VisitEncrust`] trait instead.
 pub trait Encrustable {
     /// Called when obfuscating and deobfuscating data. Calling this function manually may lead to
     /// safety issues and should not be done.
@@ -187,39 +626,95 @@ pub trait Encrustable {
     /// `toggle_encrust` directly modifies the underlying data in arbitrary ways, possibly making it
     /// unsafe to use. This function should only ever be called by encrust to obfuscate objects or
     /// deobfuscate them for reading.
-    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl RngCore);
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream);
+
+    /// Called by arrays and vectors to obfuscate and deobfuscate their elements. The default
+    /// implementation calls `toggle_encrust` for each element, while primitive integers override it
+    /// to XOR the whole slice against one continuous keystream.
+    ///
+    /// # Safety
+    /// See `toggle_encrust`.
+    #[doc(hidden)]
+    unsafe fn toggle_encrust_slice(slice: &mut [Self], encrust_rng: &mut impl Keystream)
+    where
+        Self: Sized,
+    {
+        for element in slice {
+            // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
+            // changes are reverted before granting access to the underlying memory again.
+            unsafe {
+                element.toggle_encrust(encrust_rng);
+            }
+        }
+    }
+
+    /// Calls `visitor` with the address and size of every heap allocation holding obfuscated data.
+    /// Used to lock and mark the memory when the `harden` feature is enabled. The derive macro
+    /// generates an implementation visiting all fields.
+    #[doc(hidden)]
+    fn visit_allocations(&mut self, _visitor: &mut dyn FnMut(*const u8, usize)) {}
+
+    /// Set to `true` by types that must be deobfuscated before they can be zeroized and dropped,
+    /// such as types obfuscating their heap pointers. The derive macro sets this if any field
+    /// requires it.
+    #[doc(hidden)]
+    const DECRUST_BEFORE_DROP: bool = false;
 }
 
 macro_rules! encrustable_number {
-    ( $( $t:ty ),* ) => {
-        $(
-            impl Encrustable for $t {
-                unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl ::rand::RngCore) {
-                    let mut bytes = self.to_le_bytes();
-
-                    // Using 8 bytes as most numbers that will be used with encrust are (most
-                    // likely) 64-bit or smaller.
-                    let mut key: [u8; 8] = [0; 8];
-                    for chunk in bytes.chunks_mut(8) {
-                        encrust_rng.fill_bytes(&mut key);
-                        for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
-                            *byte ^= byte_key;
-                        }
+    ( @impl $t:ty, $width:expr ) => {
+        impl Encrustable for $t {
+            unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+                let mut bytes = self.to_le_bytes();
+
+                // Using 8 bytes as most numbers that will be used with encrust are (most
+                // likely) 64-bit or smaller.
+                let mut key: [u8; 8] = [0; 8];
+                for chunk in bytes.chunks_mut(8) {
+                    encrust_rng.fill(&mut key);
+                    for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
+                        *byte ^= byte_key;
                     }
+                }
+                key.zeroize();
 
-                    *self = Self::from_le_bytes(bytes);
+                *self = Self::from_le_bytes(bytes);
+            }
+
+            unsafe fn toggle_encrust_slice(slice: &mut [Self], encrust_rng: &mut impl Keystream) {
+                const WIDTH: usize = $width;
+
+                // The keystream is drawn 64 bytes at a time, and each number is XORed with the
+                // first `size_of::<Self>()` bytes of the next `WIDTH` bytes of it.
+                let mut key: [u8; 64] = [0; 64];
+                for chunk in slice.chunks_mut(64 / WIDTH) {
+                    encrust_rng.fill(&mut key);
+                    for (n, n_key) in chunk.iter_mut().zip(key.chunks_exact(WIDTH)) {
+                        let mut bytes = [0; size_of::<Self>()];
+                        bytes.copy_from_slice(&n_key[..size_of::<Self>()]);
+                        *n ^= Self::from_le_bytes(bytes);
+                    }
                 }
+                key.zeroize();
             }
+        }
+    };
+    ( $( $t:ty ),* ) => {
+        $(
+            encrustable_number!(@impl $t, size_of::<$t>());
         )*
     };
 }
 
-encrustable_number!(
-    u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize
-);
+encrustable_number!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128);
+
+// `usize` and `isize` use 8 bytes of keystream per number regardless of the pointer width, so data
+// obfuscated by the macros on a 64-bit host is deobfuscated correctly on 32-bit targets.
+encrustable_number!(@impl usize, 8);
+encrustable_number!(@impl isize, 8);
 
 impl Encrustable for String {
-    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl RngCore) {
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
         // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
         // changes are reverted before granting access to the underlying memory again.
         let bytes = unsafe { self.as_mut_vec() };
@@ -228,11 +723,18 @@ impl Encrustable for String {
         // the tested x86-64 systems.
         let mut key: [u8; 16] = [0; 16];
         for chunk in bytes.chunks_mut(16) {
-            encrust_rng.fill_bytes(&mut key);
+            encrust_rng.fill(&mut key);
             for (byte, byte_key) in chunk.iter_mut().zip(key.iter()) {
                 *byte ^= byte_key;
             }
         }
+        key.zeroize();
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        if self.capacity() > 0 {
+            visitor(self.as_ptr(), self.capacity());
+        }
     }
 }
 
@@ -240,13 +742,19 @@ impl<T, const N: usize> Encrustable for [T; N]
 where
     T: Encrustable,
 {
-    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl RngCore) {
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
+        // changes are reverted before granting access to the underlying memory again.
+        unsafe {
+            T::toggle_encrust_slice(self, encrust_rng);
+        }
+    }
+
+    const DECRUST_BEFORE_DROP: bool = T::DECRUST_BEFORE_DROP;
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
         for element in self {
-            // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
-            // changes are reverted before granting access to the underlying memory again.
-            unsafe {
-                element.toggle_encrust(encrust_rng);
-            }
+            element.visit_allocations(visitor);
         }
     }
 }
@@ -255,19 +763,32 @@ impl<T> Encrustable for Vec<T>
 where
     T: Encrustable,
 {
-    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl RngCore) {
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
+        // changes are reverted before granting access to the underlying memory again.
+        unsafe {
+            T::toggle_encrust_slice(self, encrust_rng);
+        }
+    }
+
+    const DECRUST_BEFORE_DROP: bool = T::DECRUST_BEFORE_DROP;
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        let size = self.capacity() * size_of::<T>();
+        if size > 0 {
+            visitor(self.as_ptr().cast(), size);
+        }
+
         for element in self {
-            // Safety: This modifies the underlying bytes directly, which is unsafe. However, the
-            // changes are reverted before granting access to the underlying memory again.
-            unsafe {
-                element.toggle_encrust(encrust_rng);
-            }
+            element.visit_allocations(visitor);
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::RngCore;
+
     use super::*;
 
     const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
@@ -293,7 +814,7 @@ mod tests {
 
                 {
                     let seed = get_seed();
-                    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+                    let mut encrust_rng = SmallRngBackend::keystream(&seed);
                     let mut encrusted_data: $t = 0;
 
                     // Safety: Testing from_encrusted_data requires pre-encrusted data, which is
@@ -301,7 +822,7 @@ mod tests {
                     // `toggle_encrust` again.
                     let mut encrusted = unsafe {
                         encrusted_data.toggle_encrust(&mut encrust_rng);
-                        Encrusted::<$t>::from_encrusted_data(encrusted_data, seed)
+                        Encrusted::<$t>::from_encrusted_data(encrusted_data, seed, SmallRngBackend::VERSION)
                     };
 
                     assert_ne!(encrusted.data, 0);
@@ -340,7 +861,7 @@ mod tests {
     #[test]
     fn test_strings_from_encrusted() {
         let seed = get_seed();
-        let mut encrust_rng = SmallRng::seed_from_u64(seed);
+        let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
         let mut encrusted_string = TEST_STRING.to_string();
 
@@ -348,7 +869,11 @@ mod tests {
         // operation. The data will not be available without calling `toggle_encrust` again.
         let mut encrusted = unsafe {
             encrusted_string.toggle_encrust(&mut encrust_rng);
-            Encrusted::from_encrusted_data(encrusted_string, seed)
+            Encrusted::<String>::from_encrusted_data(
+                encrusted_string,
+                seed,
+                SmallRngBackend::VERSION,
+            )
         };
 
         assert_ne!(encrusted.data.as_bytes(), TEST_STRING.as_bytes());
@@ -382,7 +907,7 @@ mod tests {
     #[test]
     fn test_arrays_from_encrusted() {
         let seed = get_seed();
-        let mut encrust_rng = SmallRng::seed_from_u64(seed);
+        let mut encrust_rng = SmallRngBackend::keystream(&seed);
         let orig_array: [u8; 45] = [
             0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
             24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44,
@@ -394,7 +919,11 @@ mod tests {
         // operation. The data will not be available without calling `toggle_encrust` again.
         let mut encrusted = unsafe {
             encrusted_array.toggle_encrust(&mut encrust_rng);
-            Encrusted::from_encrusted_data(encrusted_array, seed)
+            Encrusted::<[u8; 45]>::from_encrusted_data(
+                encrusted_array,
+                seed,
+                SmallRngBackend::VERSION,
+            )
         };
 
         assert_ne!(encrusted.data, orig_array);
@@ -407,6 +936,50 @@ mod tests {
         assert_ne!(encrusted.data, orig_array);
     }
 
+    #[test]
+    fn test_number_slice_keystream() {
+        let orig_array: [u32; 40] =
+            core::array::from_fn(|i| u32::try_from(i).unwrap() * 0x0101_0101);
+        let encrusted = Encrusted::new(orig_array, get_seed());
+
+        // The array is XORed against one continuous keystream, not one draw per number.
+        let mut keystream = [0u8; 40 * 4];
+        SmallRngBackend::keystream(&get_seed()).fill(&mut keystream);
+        for ((encrusted, orig), key) in encrusted
+            .data
+            .iter()
+            .zip(orig_array)
+            .zip(keystream.chunks_exact(4))
+        {
+            assert_eq!(
+                orig ^ u32::from_le_bytes(key.try_into().unwrap()),
+                *encrusted
+            );
+        }
+    }
+
+    #[test]
+    fn test_usize_slice_keystream() {
+        let orig_array: [usize; 20] = core::array::from_fn(|i| i * 0x0101_0101);
+        let encrusted = Encrusted::new(orig_array, get_seed());
+
+        // Every `usize` uses 8 bytes of the keystream regardless of the pointer width, keeping the
+        // bytes matching its size.
+        let mut keystream = [0u8; 20 * 8];
+        SmallRngBackend::keystream(&get_seed()).fill(&mut keystream);
+        for ((encrusted, orig), key) in encrusted
+            .data
+            .iter()
+            .zip(orig_array)
+            .zip(keystream.chunks_exact(8))
+        {
+            assert_eq!(
+                orig ^ usize::from_le_bytes(key[..size_of::<usize>()].try_into().unwrap()),
+                *encrusted
+            );
+        }
+    }
+
     #[test]
     fn test_vecs() {
         let orig_vec = TEST_STRING.as_bytes().to_vec();
@@ -425,7 +998,7 @@ mod tests {
     #[test]
     fn test_vecs_from_encrusted() {
         let seed = get_seed();
-        let mut encrust_rng = SmallRng::seed_from_u64(seed);
+        let mut encrust_rng = SmallRngBackend::keystream(&seed);
         let orig_vec = TEST_STRING.as_bytes().to_vec();
 
         let mut encrusted_vec = orig_vec.clone();
@@ -434,7 +1007,7 @@ mod tests {
         // operation. The data will not be available without calling `toggle_encrust` again.
         let mut encrusted = unsafe {
             encrusted_vec.toggle_encrust(&mut encrust_rng);
-            Encrusted::from_encrusted_data(encrusted_vec, seed)
+            Encrusted::<Vec<u8>>::from_encrusted_data(encrusted_vec, seed, SmallRngBackend::VERSION)
         };
 
         assert_ne!(encrusted.data, orig_vec);
@@ -451,14 +1024,14 @@ mod tests {
     fn test_reseed() {
         let num = 828_627_825_u64;
         let mut encrusted = Encrusted::new(num, get_seed());
-        let orig_seed = encrusted.seed;
+        let orig_seed = encrusted.key;
         let mut rng = rand::rng();
 
         encrusted.reseed(rng.next_u64());
 
         // May fail, but the seed is so large that a collision is highly unlikely if it is selected
         // randomly.
-        assert_ne!(encrusted.seed, orig_seed);
+        assert_ne!(encrusted.key, orig_seed);
 
         {
             let decrusted = encrusted.decrust();
@@ -466,6 +1039,149 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decrust_copy() {
+        let encrusted = Encrusted::new(TEST_STRING.to_string(), get_seed());
+
+        {
+            let copy = encrusted.decrust_copy();
+            assert_eq!(TEST_STRING, copy.as_str());
+            assert_ne!(TEST_STRING.as_bytes(), encrusted.data.as_bytes());
+        }
+
+        assert!(encrusted.peek(|string| string == TEST_STRING));
+        assert_ne!(TEST_STRING.as_bytes(), encrusted.data.as_bytes());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_key_rotation() {
+        let num = 828_627_825_u64;
+        let mut encrusted = Encrusted::new_random(num);
+        assert!(!encrusted.key_rotation());
+
+        let orig_seed = encrusted.key;
+        let orig_data = encrusted.data;
+        assert_eq!(*encrusted.decrust(), num);
+
+        // Without key rotation, the data is obfuscated using the same key.
+        assert_eq!(encrusted.key, orig_seed);
+        assert_eq!(encrusted.data, orig_data);
+
+        encrusted.set_key_rotation(true);
+        assert_eq!(*encrusted.decrust(), num);
+
+        // May fail, but the seed is so large that a collision is highly unlikely if it is selected
+        // randomly.
+        assert_ne!(encrusted.key, orig_seed);
+        assert_ne!(encrusted.data, orig_data);
+        assert_eq!(*encrusted.decrust(), num);
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn test_chacha20_backend() {
+        let key = [0x5a; 32];
+        let mut encrusted = Encrusted::<_, ChaCha20Backend>::with_key(TEST_STRING.to_string(), key);
+        assert_ne!(encrusted.data.as_bytes(), TEST_STRING.as_bytes());
+
+        {
+            let decrusted = encrusted.decrust();
+            assert_eq!(*decrusted, TEST_STRING);
+        }
+
+        assert_ne!(encrusted.data.as_bytes(), TEST_STRING.as_bytes());
+
+        encrusted.reseed([0xa5; 32]);
+        assert_eq!(*encrusted.decrust(), TEST_STRING);
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn test_aes_ctr_backend() {
+        let key = [0x5a; 32];
+        let mut encrusted = Encrusted::<_, AesCtrBackend>::with_key(TEST_STRING.to_string(), key);
+        assert_ne!(encrusted.data.as_bytes(), TEST_STRING.as_bytes());
+
+        {
+            let decrusted = encrusted.decrust();
+            assert_eq!(*decrusted, TEST_STRING);
+        }
+
+        assert_ne!(encrusted.data.as_bytes(), TEST_STRING.as_bytes());
+
+        encrusted.reseed([0xa5; 32]);
+        assert_eq!(*encrusted.decrust(), TEST_STRING);
+    }
+
+    /// The AES keystream must not depend on how the output is split into calls to `fill`.
+    #[cfg(feature = "aes")]
+    #[test]
+    fn aes_ctr_keystream_is_continuous() {
+        let key = [0x5a; 32];
+        let mut whole = [0u8; 45];
+        AesCtrBackend::keystream(&key).fill(&mut whole);
+
+        let mut split = [0u8; 45];
+        let mut keystream = AesCtrBackend::keystream(&key);
+        for chunk in split.chunks_mut(7) {
+            keystream.fill(chunk);
+        }
+
+        assert_eq!(whole, split);
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn test_authenticated() {
+        let mut encrusted = Encrusted::new_authenticated(TEST_STRING.to_string(), get_seed());
+        assert!(encrusted.is_authenticated());
+
+        {
+            let mut decrusted = encrusted
+                .try_decrust()
+                .expect("Data has not been tampered with");
+            assert_eq!(*decrusted, TEST_STRING);
+            decrusted.push('!');
+        }
+
+        assert_eq!(
+            *encrusted
+                .try_decrust()
+                .expect("Tag is updated after modification"),
+            format!("{TEST_STRING}!")
+        );
+
+        encrusted.reseed(get_seed() + 1);
+        assert!(encrusted.try_decrust().is_ok());
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn test_authenticated_detects_tampering() {
+        let mut encrusted = Encrusted::new_authenticated([1u32, 2, 3], get_seed());
+        encrusted.data[1] ^= 1;
+
+        assert_eq!(
+            encrusted.try_decrust().err(),
+            Some(IntegrityError::Mismatch)
+        );
+
+        encrusted.data[1] ^= 1;
+        assert_eq!(*encrusted.try_decrust().unwrap(), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn test_not_authenticated() {
+        let mut encrusted = Encrusted::new(1u8, get_seed());
+        assert!(!encrusted.is_authenticated());
+        assert_eq!(
+            encrusted.try_decrust().err(),
+            Some(IntegrityError::NotAuthenticated)
+        );
+    }
+
     /// Test to make sure that a previously encrusted object can be decrusted with the current
     /// version of `encrust`.
     #[test]
@@ -473,7 +1189,7 @@ mod tests {
         // Safety: Comparing a `String` with invalid UTF-8 in a test should hopefully at worst crash
         // the test.
         let mut test_string = unsafe {
-            Encrusted::from_encrusted_data(
+            Encrusted::<String>::from_encrusted_data(
                 String::from_utf8_unchecked(
                     [
                         55u8, 10u8, 35u8, 94u8, 130u8, 81u8, 207u8, 225u8, 64u8, 17u8, 143u8, 78u8,
@@ -489,10 +1205,110 @@ mod tests {
                     reason = "Arbitrary number chosen at random with no further meaning."
                 )]
                 5233902475398815152u64,
+                1,
             )
         };
 
         let decrusted_test_string = test_string.decrust();
         assert_eq!(*decrusted_test_string, TEST_STRING);
     }
+
+    /// Backend whose second version of the algorithm adds one to the seed used by the first
+    /// version.
+    struct VersionedBackend;
+
+    impl Backend for VersionedBackend {
+        type Key = u64;
+        type Keystream = SmallRngKeystream;
+
+        const VERSION: u8 = 2;
+        const KEY_SIZE: usize = 8;
+
+        fn keystream(key: &Self::Key) -> Self::Keystream {
+            SmallRngBackend::keystream(&key.wrapping_add(1))
+        }
+
+        fn versioned_keystream(key: &Self::Key, version: u8) -> Option<Self::Keystream> {
+            match version {
+                1 => Some(SmallRngBackend::keystream(key)),
+                2 => Some(Self::keystream(key)),
+                _ => None,
+            }
+        }
+
+        fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+            SmallRngBackend::mac_keystream(&key.wrapping_add(1))
+        }
+
+        fn key_from_material(material: &[u8]) -> Self::Key {
+            SmallRngBackend::key_from_material(material)
+        }
+    }
+
+    /// Returns `TEST_STRING` obfuscated using the first version of `VersionedBackend`.
+    fn obfuscated_with_first_version() -> String {
+        let mut data = TEST_STRING.to_string();
+
+        // Safety: The string is only deobfuscated by `Encrusted`.
+        unsafe {
+            data.toggle_encrust(&mut SmallRngBackend::keystream(&get_seed()));
+        }
+
+        data
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_upgrade_version() {
+        // Safety: The data is obfuscated using the first version of the backend.
+        let mut encrusted = unsafe {
+            Encrusted::<String, VersionedBackend>::from_encrusted_data(
+                obfuscated_with_first_version(),
+                get_seed(),
+                1,
+            )
+        };
+
+        let mut expected = TEST_STRING.to_string();
+        // Safety: The expected data is only compared as bytes.
+        unsafe {
+            expected.toggle_encrust(&mut VersionedBackend::keystream(&get_seed()));
+        }
+        assert_eq!(expected.as_bytes(), encrusted.data.as_bytes());
+
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        assert!(
+            Encrusted::<String, VersionedBackend>::from_versioned(
+                obfuscated_with_first_version(),
+                get_seed(),
+                3
+            )
+            .is_none()
+        );
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn test_upgrade_version_authenticated() {
+        let data = obfuscated_with_first_version();
+        let mut mac = IntegrityMac::new::<VersionedBackend>(&get_seed());
+        data.authenticate(&mut mac);
+        let tag = mac.finalize();
+
+        let mut encrusted = Encrusted::<String, VersionedBackend>::from_versioned_authenticated(
+            data,
+            get_seed(),
+            tag,
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(IntegrityError::Mismatch),
+            encrusted.try_decrust().err()
+        );
+    }
 }
@@ -0,0 +1,424 @@
+//! Synchronized access to encrusted data shared between threads.
+
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use zeroize::Zeroize;
+
+use crate::{Backend, Decrusted, Encrustable, Encrusted, SmallRngBackend};
+
+/// A reader-writer lock for [`Encrusted`] data, allowing several threads to read the deobfuscated
+/// data at the same time.
+///
+/// The data is deobfuscated when the first [`EncrustedReadGuard`] is created and obfuscated again
+/// when the last one is dropped, so concurrent readers share a single deobfuscated view instead of
+/// taking turns deobfuscating the data. Writers get exclusive access through
+/// [`EncrustedWriteGuard`], which works like [`Decrusted`], including the functions growing vectors
+/// and strings that zeroize the old allocation.
+///
+/// As with [`Encrusted::decrust`], the integrity of authenticated data is not verified when it is
+/// deobfuscated, but the MAC is updated when the data is obfuscated again.
+///
+/// Panics in threads holding a guard do not poison the lock, as the data is obfuscated again when
+/// the guard is dropped during unwinding.
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, EncrustedLock};
+///
+/// let api_token = EncrustedLock::new(Encrusted::new("A secret token".to_string(), 0xc0ffee));
+///
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| assert_eq!("A secret token", api_token.read().as_str()));
+///     }
+/// });
+///
+/// // Zeroizes the old allocation if the string has to grow.
+/// api_token.write().push_str(", modified");
+/// assert_eq!("A secret token, modified", api_token.read().as_str());
+/// ```
+pub struct EncrustedLock<T, B = SmallRngBackend>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    lock: RwLock<()>,
+    readers: Mutex<usize>,
+    encrusted: UnsafeCell<Encrusted<T, B>>,
+}
+
+// SAFETY:
+// The `Encrusted` object is only modified by the first and last reader while holding the `readers`
+// mutex, or by a writer holding the write lock, so it is never modified while references to it
+// exist in other threads. Readers in multiple threads may access `T` at the same time, requiring
+// `Sync`, and the data may be modified and dropped by any thread, requiring `Send`.
+unsafe impl<T, B> Sync for EncrustedLock<T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+    Encrusted<T, B>: Send + Sync,
+{
+}
+
+impl<T, B> EncrustedLock<T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    /// Creates a new lock protecting `encrusted`.
+    pub const fn new(encrusted: Encrusted<T, B>) -> Self {
+        Self {
+            lock: RwLock::new(()),
+            readers: Mutex::new(0),
+            encrusted: UnsafeCell::new(encrusted),
+        }
+    }
+
+    /// Consumes the lock, returning the obfuscated data.
+    pub fn into_inner(self) -> Encrusted<T, B> {
+        self.encrusted.into_inner()
+    }
+
+    /// Returns a mutable reference to the obfuscated data. No locking is needed, as the mutable
+    /// borrow guarantees that no guards exist.
+    pub fn get_mut(&mut self) -> &mut Encrusted<T, B> {
+        self.encrusted.get_mut()
+    }
+
+    /// Locks the data for shared read access, blocking the current thread until no writer holds
+    /// the lock. The data is deobfuscated if no other readers are currently accessing it.
+    pub fn read(&self) -> EncrustedReadGuard<'_, T, B> {
+        let guard = self.lock.read().unwrap_or_else(PoisonError::into_inner);
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if *readers == 0 {
+            // SAFETY:
+            // There are no other readers, and the read lock prevents writers, so no references to
+            // the `Encrusted` object exist.
+            unsafe {
+                (*self.encrusted.get()).expose();
+            }
+        }
+        *readers += 1;
+
+        EncrustedReadGuard {
+            lock: self,
+            _guard: guard,
+        }
+    }
+
+    /// Locks the data for exclusive write access, blocking the current thread until no other
+    /// readers or writers hold the lock.
+    pub fn write(&self) -> EncrustedWriteGuard<'_, T, B> {
+        let guard = self.lock.write().unwrap_or_else(PoisonError::into_inner);
+
+        // SAFETY:
+        // The write lock guarantees that no other references to the `Encrusted` object exist for
+        // as long as the guard lives.
+        let encrusted = unsafe { &mut *self.encrusted.get() };
+
+        EncrustedWriteGuard {
+            decrusted: encrusted.decrust(),
+            _guard: guard,
+        }
+    }
+}
+
+impl<T, B> From<Encrusted<T, B>> for EncrustedLock<T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    fn from(encrusted: Encrusted<T, B>) -> Self {
+        Self::new(encrusted)
+    }
+}
+
+/// Shared read access to data protected by an [`EncrustedLock`], created by
+/// [`EncrustedLock::read`]. The data is obfuscated again when the last read guard is dropped.
+pub struct EncrustedReadGuard<'lock, T, B = SmallRngBackend>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    lock: &'lock EncrustedLock<T, B>,
+    _guard: RwLockReadGuard<'lock, ()>,
+}
+
+impl<T, B> Drop for EncrustedReadGuard<'_, T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    fn drop(&mut self) {
+        let mut readers = self
+            .lock
+            .readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *readers -= 1;
+
+        if *readers == 0 {
+            // SAFETY:
+            // This was the last reader, and the read lock is held until after this function
+            // returns, so no other references to the `Encrusted` object exist.
+            unsafe {
+                (*self.lock.encrusted.get()).conceal();
+            }
+        }
+    }
+}
+
+impl<T, B> Deref for EncrustedReadGuard<'_, T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY:
+        // The data is only modified when there are no read guards or while holding the write lock,
+        // neither of which is possible while this guard exists.
+        unsafe { &(*self.lock.encrusted.get()).data }
+    }
+}
+
+/// Exclusive access to data protected by an [`EncrustedLock`], created by
+/// [`EncrustedLock::write`]. The data is obfuscated again when the guard is dropped.
+///
+/// Like [`Decrusted`], write guards for vectors and strings have their own `reserve`, `push`,
+/// `truncate` and similar functions, taking precedence over the functions reached through
+/// `DerefMut`. They zeroize the old allocation when the data grows, and the removed data when
+/// truncating.
+pub struct EncrustedWriteGuard<'lock, T, B = SmallRngBackend>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    // Fields are dropped in declaration order, so the data is obfuscated before the lock is
+    // released.
+    decrusted: Decrusted<'lock, T, B>,
+    _guard: RwLockWriteGuard<'lock, ()>,
+}
+
+impl<T, B> Deref for EncrustedWriteGuard<'_, T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.decrusted
+    }
+}
+
+impl<T, B> DerefMut for EncrustedWriteGuard<'_, T, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.decrusted
+    }
+}
+
+impl<T, B> EncrustedWriteGuard<'_, Vec<T>, B>
+where
+    T: Encrustable + Zeroize,
+    B: Backend,
+{
+    /// Works like [`Vec::reserve`], but zeroizes the old allocation if the vector grows.
+    pub fn reserve(&mut self, additional: usize) {
+        self.decrusted.reserve(additional);
+    }
+
+    /// Works like [`Vec::push`], but zeroizes the old allocation if the vector grows.
+    pub fn push(&mut self, value: T) {
+        self.decrusted.push(value);
+    }
+
+    /// Works like [`Vec::insert`], but zeroizes the old allocation if the vector grows.
+    ///
+    /// # Panics
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, element: T) {
+        self.decrusted.insert(index, element);
+    }
+
+    /// Works like [`Vec::extend_from_slice`], but zeroizes the old allocation if the vector grows.
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.decrusted.extend_from_slice(other);
+    }
+
+    /// Works like [`Vec::resize`], but zeroizes the old allocation if the vector grows, and the
+    /// removed elements if it shrinks.
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        self.decrusted.resize(new_len, value);
+    }
+
+    /// Works like [`Vec::truncate`], but zeroizes the removed elements before they are dropped.
+    pub fn truncate(&mut self, len: usize) {
+        self.decrusted.truncate(len);
+    }
+
+    /// Works like [`Vec::clear`], but zeroizes the removed elements before they are dropped.
+    pub fn clear(&mut self) {
+        self.decrusted.clear();
+    }
+}
+
+impl<B> EncrustedWriteGuard<'_, String, B>
+where
+    B: Backend,
+{
+    /// Works like [`String::reserve`], but zeroizes the old allocation if the string grows.
+    pub fn reserve(&mut self, additional: usize) {
+        self.decrusted.reserve(additional);
+    }
+
+    /// Works like [`String::push`], but zeroizes the old allocation if the string grows.
+    pub fn push(&mut self, ch: char) {
+        self.decrusted.push(ch);
+    }
+
+    /// Works like [`String::push_str`], but zeroizes the old allocation if the string grows.
+    pub fn push_str(&mut self, string: &str) {
+        self.decrusted.push_str(string);
+    }
+
+    /// Works like [`String::insert`], but zeroizes the old allocation if the string grows.
+    ///
+    /// # Panics
+    /// Panics if `index` is not a character boundary.
+    pub fn insert(&mut self, index: usize, ch: char) {
+        self.decrusted.insert(index, ch);
+    }
+
+    /// Works like [`String::insert_str`], but zeroizes the old allocation if the string grows.
+    ///
+    /// # Panics
+    /// Panics if `index` is not a character boundary.
+    pub fn insert_str(&mut self, index: usize, string: &str) {
+        self.decrusted.insert_str(index, string);
+    }
+
+    /// Works like [`String::truncate`], but zeroizes the removed bytes.
+    ///
+    /// # Panics
+    /// Panics if `new_len` is not a character boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        self.decrusted.truncate(new_len);
+    }
+
+    /// Works like [`String::clear`], but zeroizes the removed bytes.
+    pub fn clear(&mut self) {
+        self.decrusted.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog";
+
+    #[test]
+    fn last_reader_reencrusts() {
+        let mut lock = EncrustedLock::new(Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11));
+
+        {
+            let first = lock.read();
+            let second = lock.read();
+            assert_eq!(TEST_STRING, first.as_str());
+
+            drop(first);
+            assert_eq!(TEST_STRING, second.as_str());
+        }
+
+        assert_ne!(TEST_STRING.as_bytes(), lock.get_mut().data.as_bytes());
+        assert_eq!(TEST_STRING, lock.get_mut().decrust().as_str());
+    }
+
+    #[test]
+    fn concurrent_readers() {
+        const THREADS: usize = 8;
+
+        let lock = EncrustedLock::new(Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11));
+        let barrier = Barrier::new(THREADS);
+
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let guard = lock.read();
+                    // Wait until all threads hold a read guard at the same time.
+                    barrier.wait();
+                    assert_eq!(TEST_STRING, guard.as_str());
+                });
+            }
+        });
+
+        let mut encrusted = lock.into_inner();
+        assert_ne!(TEST_STRING.as_bytes(), encrusted.data.as_bytes());
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[test]
+    fn readers_and_writers() {
+        let lock = EncrustedLock::new(Encrusted::new(0u64, 0x2357_bd11));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        *lock.write() += 1;
+                        assert!(*lock.read() > 0);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(400, *lock.read());
+    }
+
+    #[test]
+    fn write_guard_growth() {
+        let lock = EncrustedLock::new(Encrusted::new(String::new(), 0x2357_bd11));
+
+        {
+            let mut guard = lock.write();
+            guard.push_str(&TEST_STRING[1..]);
+            guard.insert(0, 'T');
+            guard.push('!');
+        }
+        assert_eq!(format!("{TEST_STRING}!"), lock.read().as_str());
+
+        lock.write().truncate(3);
+        assert_eq!("The", lock.read().as_str());
+
+        let lock = EncrustedLock::new(Encrusted::new(vec![1u16], 0x2357_bd11));
+        {
+            let mut guard = lock.write();
+            guard.extend_from_slice(&[2, 3]);
+            guard.push(4);
+            guard.resize(6, 5);
+        }
+        assert_eq!(vec![1, 2, 3, 4, 5, 5], *lock.read());
+
+        lock.write().clear();
+        assert!(lock.read().is_empty());
+    }
+}
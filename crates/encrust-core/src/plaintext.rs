@@ -0,0 +1,421 @@
+//! Deserializing plaintext values, such as secrets in configuration files, directly into
+//! [`Encrusted`] objects using `serde`.
+//!
+//! Strings and bytes are obfuscated using a random key as the deserializer hands them over,
+//! without creating a deobfuscated `String` or `Vec<u8>`. Values owned by the deserializer are
+//! zeroized after being obfuscated, while borrowed values are part of the input or a buffer of the
+//! deserializer, which are outside the control of encrust.
+//!
+//! Values are deserialized using `deserialize_any`, as deserializers asked for a specific type may
+//! include values of other types in their errors. This requires a self-describing format, such as
+//! JSON, TOML or YAML.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    Deserializer,
+    de::{self, DeserializeSeed, SeqAccess, Unexpected, Visitor},
+};
+use zeroize::Zeroize;
+
+use crate::{Backend, Bytes, Encrusted, EncrustedBytesBuilder, EncrustedStringBuilder};
+
+/// Trait implemented by [`Encrusted`] objects that can be deserialized from plaintext values.
+pub trait DeserializePlaintext: Sized {
+    /// Deserializes a plaintext value from `deserializer`, obfuscating it using a random key.
+    ///
+    /// # Errors
+    /// Returns an error if `deserializer` does not hold a value of the right type. The error does
+    /// not contain the value.
+    fn deserialize_plaintext<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// Deserializes a plaintext value into an [`Encrusted`] object. Intended to be used with
+/// `#[serde(deserialize_with = "...")]` on fields holding secrets.
+///
+/// # Errors
+/// See [`DeserializePlaintext::deserialize_plaintext`].
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, deserialize_plaintext};
+///
+/// #[derive(serde::Deserialize)]
+/// struct Config {
+///     user: String,
+///     #[serde(deserialize_with = "deserialize_plaintext")]
+///     password: Encrusted<String>,
+/// }
+///
+/// let mut config: Config =
+///     serde_json::from_str(r#"{"user": "admin", "password": "A secret password"}"#).unwrap();
+/// assert_eq!("admin", config.user);
+/// assert_eq!("A secret password", config.password.decrust().as_str());
+/// ```
+pub fn deserialize_plaintext<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializePlaintext,
+{
+    T::deserialize_plaintext(deserializer)
+}
+
+/// [`DeserializeSeed`] deserializing plaintext values into `T`, for use with deserializers that
+/// are driven manually.
+pub struct PlaintextSeed<T>(PhantomData<T>);
+
+impl<T> PlaintextSeed<T> {
+    /// Creates a new `PlaintextSeed`.
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for PlaintextSeed<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'de, T> DeserializeSeed<'de> for PlaintextSeed<T>
+where
+    T: DeserializePlaintext,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_plaintext(deserializer)
+    }
+}
+
+/// Implements the listed functions of `Visitor` by returning an error describing the value as
+/// `$unexpected`. The default implementations include the value in the error, which may be a
+/// secret.
+macro_rules! reject_values {
+    ( $unexpected:literal; $( $visit:ident($t:ty) ),* ) => {
+        $(
+            fn $visit<E>(self, _v: $t) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Err(E::invalid_type(Unexpected::Other($unexpected), &self))
+            }
+        )*
+    };
+}
+
+struct StringVisitor<B>(PhantomData<B>);
+
+impl<B> Visitor<'_> for StringVisitor<B>
+where
+    B: Backend,
+{
+    type Value = Encrusted<String, B>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    reject_values!(
+        "non-string value";
+        visit_bool(bool),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f64(f64)
+    );
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let mut builder = EncrustedStringBuilder::with_random_key();
+        builder.append(v);
+
+        Ok(builder.finish())
+    }
+
+    fn visit_string<E>(self, mut v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let encrusted = self.visit_str(&v);
+        v.zeroize();
+
+        encrusted
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // The bytes are not included in the error, as they may hold a secret.
+        let v = std::str::from_utf8(v)
+            .map_err(|_| E::invalid_value(Unexpected::Other("invalid UTF-8"), &self))?;
+
+        self.visit_str(v)
+    }
+
+    fn visit_byte_buf<E>(self, mut v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let encrusted = self.visit_bytes(&v);
+        v.zeroize();
+
+        encrusted
+    }
+}
+
+impl<B> DeserializePlaintext for Encrusted<String, B>
+where
+    B: Backend,
+{
+    fn deserialize_plaintext<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(StringVisitor(PhantomData))
+    }
+}
+
+struct BytesVisitor<B>(PhantomData<B>);
+
+impl<'de, B> Visitor<'de> for BytesVisitor<B>
+where
+    B: Backend,
+{
+    type Value = Encrusted<Bytes, B>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("bytes or a string")
+    }
+
+    reject_values!(
+        "non-string value";
+        visit_bool(bool),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f64(f64)
+    );
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let mut builder = EncrustedBytesBuilder::with_random_key();
+        builder.append(v);
+
+        Ok(builder.finish())
+    }
+
+    fn visit_byte_buf<E>(self, mut v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let encrusted = self.visit_bytes(&v);
+        v.zeroize();
+
+        encrusted
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_bytes(v.as_bytes())
+    }
+
+    fn visit_string<E>(self, mut v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let encrusted = self.visit_str(&v);
+        v.zeroize();
+
+        encrusted
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // Each byte is obfuscated as soon as it is read.
+        let mut builder = EncrustedBytesBuilder::with_random_key();
+        while let Some(mut byte) = seq.next_element_seed(ByteSeed)? {
+            builder.append(&[byte]);
+            byte.zeroize();
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+/// [`DeserializeSeed`] for the elements of byte sequences, which does not include the element in
+/// errors, unlike the implementation of `Deserialize` for `u8`.
+struct ByteSeed;
+
+impl<'de> DeserializeSeed<'de> for ByteSeed {
+    type Value = u8;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ByteVisitor)
+    }
+}
+
+struct ByteVisitor;
+
+impl ByteVisitor {
+    fn byte<E>(self, v: impl TryInto<u8>) -> Result<u8, E>
+    where
+        E: de::Error,
+    {
+        v.try_into()
+            .map_err(|_| E::invalid_value(Unexpected::Other("integer out of range"), &self))
+    }
+}
+
+impl Visitor<'_> for ByteVisitor {
+    type Value = u8;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.byte(v)
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.byte(v)
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.byte(v)
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.byte(v)
+    }
+
+    reject_values!(
+        "non-integer value";
+        visit_bool(bool),
+        visit_f64(f64),
+        visit_str(&str),
+        visit_bytes(&[u8])
+    );
+}
+
+impl<B> DeserializePlaintext for Encrusted<Bytes, B>
+where
+    B: Backend,
+{
+    fn deserialize_plaintext<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(BytesVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, de::IntoDeserializer};
+
+    use super::*;
+    use crate::EncrustedBytes;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[derive(Deserialize)]
+    struct Config {
+        #[serde(deserialize_with = "deserialize_plaintext")]
+        password: Encrusted<String>,
+        #[serde(deserialize_with = "deserialize_plaintext")]
+        key: EncrustedBytes,
+    }
+
+    #[test]
+    fn config() {
+        let json = format!(r#"{{"password": "{TEST_STRING}", "key": [1, 2, 3]}}"#);
+        let mut config: Config = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(TEST_STRING, config.password.decrust().as_str());
+        assert_eq!(vec![1, 2, 3], config.key.decrust().as_slice());
+
+        // Escaped strings are unescaped into a buffer of the deserializer instead of being
+        // borrowed from the input.
+        let json = r#"{"password": "A \"secret\"", "key": "A key"}"#;
+        let mut config: Config = serde_json::from_str(json).unwrap();
+
+        assert_eq!("A \"secret\"", config.password.decrust().as_str());
+        assert_eq!(b"A key", config.key.decrust().as_slice());
+    }
+
+    #[test]
+    fn seed() {
+        let deserializer: de::value::StringDeserializer<de::value::Error> =
+            TEST_STRING.to_string().into_deserializer();
+        let mut encrusted = PlaintextSeed::<Encrusted<String>>::new()
+            .deserialize(deserializer)
+            .unwrap();
+
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[test]
+    fn errors_do_not_contain_value() {
+        let deserializer: de::value::BytesDeserializer<de::value::Error> =
+            de::value::BytesDeserializer::new(b"secret\xff");
+        let error = PlaintextSeed::<Encrusted<String>>::new()
+            .deserialize(deserializer)
+            .err()
+            .unwrap()
+            .to_string();
+
+        assert!(!error.contains("secret"));
+
+        let error = serde_json::from_str::<Config>(r#"{"password": 1, "key": []}"#)
+            .err()
+            .unwrap();
+        assert!(error.is_data());
+
+        for json in [
+            r#"{"password": 123456, "key": []}"#,
+            r#"{"password": -123456, "key": []}"#,
+            r#"{"password": 123456.5, "key": []}"#,
+            r#"{"password": "", "key": 123456}"#,
+            r#"{"password": "", "key": [1, 123456]}"#,
+            r#"{"password": "", "key": [1, -123456]}"#,
+            r#"{"password": "", "key": [1, "123456"]}"#,
+        ] {
+            let error = serde_json::from_str::<Config>(json).err().unwrap();
+            assert!(error.is_data());
+            assert!(!error.to_string().contains("123456"), "{error}");
+        }
+    }
+}
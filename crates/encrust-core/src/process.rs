@@ -0,0 +1,182 @@
+//! Passing obfuscated secrets to child processes through their stdin.
+
+use std::{
+    io::{self, BufRead, Write},
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+use crate::{Backend, Bytes, Encrusted, EncrustedBytesBuilder, SmallRngBackend};
+
+/// Trait implemented by [`Encrusted`] objects that can be written to a writer without leaving
+/// deobfuscated copies of the data behind.
+///
+/// Byte buffers are deobfuscated one block of 64 bytes at a time using an
+/// [`EncrustedReader`](crate::EncrustedReader), while strings are written from a deobfuscated copy
+/// that is zeroized after writing, see [`Encrusted::peek`]. The writer should not buffer the
+/// data, as its buffers are not zeroized.
+pub trait WriteDecrusted {
+    /// Writes the deobfuscated data to `writer`.
+    ///
+    /// # Errors
+    /// Returns any error returned by `writer`.
+    fn write_decrusted(&self, writer: &mut impl Write) -> io::Result<()>;
+}
+
+impl<B> WriteDecrusted for Encrusted<String, B>
+where
+    B: Backend,
+{
+    fn write_decrusted(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.peek(|string| writer.write_all(string.as_bytes()))
+    }
+}
+
+/// Writes the bytes of `reader` to `writer` one block at a time, without any additional buffers.
+fn write_blocks(mut reader: impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    loop {
+        let block = reader.fill_buf()?;
+        if block.is_empty() {
+            return Ok(());
+        }
+
+        writer.write_all(block)?;
+
+        let len = block.len();
+        reader.consume(len);
+    }
+}
+
+impl<B> WriteDecrusted for Encrusted<Bytes, B>
+where
+    B: Backend,
+{
+    fn write_decrusted(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_blocks(self.reader(), writer)
+    }
+}
+
+impl<B> WriteDecrusted for Encrusted<Vec<u8>, B>
+where
+    B: Backend,
+{
+    fn write_decrusted(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_blocks(self.reader(), writer)
+    }
+}
+
+/// Extension trait for [`Command`] to pass secrets to child processes through their stdin.
+///
+/// Secrets passed as arguments or environment variables are copied by `Command`, and are visible
+/// to other processes on many systems. Writing the secret to the stdin of the child process
+/// deobfuscates it only while it is written to the pipe.
+///
+/// # Example
+/// ```no_run
+/// use std::process::Command;
+///
+/// use encrust_core::{Encrusted, EncrustedCommandExt};
+///
+/// let token = Encrusted::new_random("A secret token".to_string());
+/// let status = Command::new("helper").spawn_with_secret(&token)?.wait()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub trait EncrustedCommandExt {
+    /// Spawns the command with its stdin connected to a pipe, writes `secret` to the pipe and
+    /// closes it, so the child process reads the secret followed by the end of its input.
+    ///
+    /// The secret is copied into an [`EncrustedBytes`](crate::EncrustedBytes) object using a
+    /// random key, which is written to the pipe by a separate thread. This allows the caller to
+    /// read the output of the child process while the child process reads a secret that does not
+    /// fit in the buffer of the pipe.
+    ///
+    /// # Errors
+    /// Returns an error if the command cannot be spawned, or if the stdin of the child process
+    /// cannot be opened, in which case the child process is killed. Errors writing the secret, for
+    /// example because the child process exits without reading it, close the pipe and are not
+    /// reported.
+    fn spawn_with_secret(&mut self, secret: &impl WriteDecrusted) -> io::Result<Child>;
+}
+
+impl EncrustedCommandExt for Command {
+    fn spawn_with_secret(&mut self, secret: &impl WriteDecrusted) -> io::Result<Child> {
+        // The builder obfuscates the secret as it is written, so the copy is never deobfuscated.
+        let mut copy = EncrustedBytesBuilder::<SmallRngBackend>::with_random_key();
+        secret.write_decrusted(&mut copy)?;
+        let copy = copy.finish();
+
+        let mut child = self.stdin(Stdio::piped()).spawn()?;
+
+        let Some(mut stdin) = child.stdin.take() else {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            return Err(io::Error::other(
+                "Unable to open stdin of the child process",
+            ));
+        };
+
+        // `ChildStdin` is not buffered, so the data is written directly to the pipe. The pipe is
+        // closed when `stdin` is dropped at the end of the thread.
+        thread::spawn(move || {
+            let _ = copy.write_decrusted(&mut stdin);
+        });
+
+        Ok(child)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::EncrustedBytes;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    fn spawn_cat(secret: &impl WriteDecrusted) -> Vec<u8> {
+        let child = Command::new("cat")
+            .stdout(Stdio::piped())
+            .spawn_with_secret(secret)
+            .unwrap();
+
+        child.wait_with_output().unwrap().stdout
+    }
+
+    #[test]
+    fn string() {
+        let secret = Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11);
+        assert_eq!(TEST_STRING.as_bytes(), spawn_cat(&secret));
+    }
+
+    #[test]
+    fn bytes() {
+        let data = TEST_STRING.repeat(100).into_bytes();
+
+        let secret = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+        assert_eq!(data, spawn_cat(&secret));
+
+        let secret = Encrusted::new(data.clone(), 0x2357_bd11);
+        assert_eq!(data, spawn_cat(&secret));
+    }
+
+    #[test]
+    fn larger_than_pipe_buffer() {
+        // Pipe buffers hold 64 KiB by default on Linux, so `cat` blocks writing its output before
+        // it has read all of the secret.
+        let data = TEST_STRING.repeat(10_000).into_bytes();
+        assert!(data.len() > 1 << 16);
+
+        let secret = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+        assert_eq!(data, spawn_cat(&secret));
+    }
+
+    #[test]
+    fn write_decrusted() {
+        let data = TEST_STRING.repeat(3).into_bytes();
+        let secret = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+
+        let mut written = Vec::new();
+        secret.write_decrusted(&mut written).unwrap();
+        assert_eq!(data, written);
+    }
+}
@@ -0,0 +1,257 @@
+//! Reading passwords from the terminal directly into obfuscated storage.
+
+use std::io::{self, Write};
+
+use zeroize::Zeroizing;
+
+use crate::{Backend, Encrusted, EncrustedStringBuilder, SmallRngBackend};
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const CTRL_U: u8 = 0x15;
+const DELETE: u8 = 0x7f;
+
+/// Writes `prompt` to stderr and reads a password from stdin, obfuscating each character as it is
+/// read, using a random key and the default [`SmallRngBackend`].
+///
+/// See [`prompt_password_with_backend`].
+///
+/// # Errors
+/// See [`prompt_password_with_backend`].
+pub fn prompt_password(prompt: &str) -> io::Result<Encrusted<String>> {
+    prompt_password_with_backend::<SmallRngBackend>(prompt)
+}
+
+/// Writes `prompt` to stderr and reads a password from stdin, obfuscating each character as it is
+/// read, using a random key and the backend `B`.
+///
+/// If stdin is a terminal, echo and line editing are turned off while the password is typed, and
+/// the keystrokes are read one byte at a time. Backspace removes the last character and Ctrl-U
+/// removes all of them, while Enter and Ctrl-D end the password. Only the bytes of the character
+/// currently being read are ever deobfuscated, no line buffer is used.
+///
+/// If stdin is not a terminal, such as when it is a pipe, the prompt is not written and the
+/// password is read up to the first line break or the end of the input, without handling control
+/// characters.
+///
+/// # Errors
+/// Returns an error if stdin cannot be read or the terminal cannot be configured, an error of the
+/// kind [`io::ErrorKind::Interrupted`] if Ctrl-C is pressed, and an error of the kind
+/// [`io::ErrorKind::InvalidData`] if the password is not valid UTF-8.
+///
+/// # Example
+/// ```no_run
+/// let mut passphrase = encrust_core::prompt_password("Passphrase: ")?;
+/// let unlocked = passphrase.decrust().as_str() == "A secret passphrase";
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn prompt_password_with_backend<B: Backend>(prompt: &str) -> io::Result<Encrusted<String, B>> {
+    // SAFETY:
+    // `isatty` only checks the file descriptor.
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return read_password(libc::STDIN_FILENO, false);
+    }
+
+    let mut stderr = io::stderr();
+    stderr.write_all(prompt.as_bytes())?;
+    stderr.flush()?;
+
+    let password = {
+        let _raw_mode = RawMode::enable(libc::STDIN_FILENO)?;
+        read_password(libc::STDIN_FILENO, true)
+    };
+
+    // The line break typed by the user is not echoed.
+    stderr.write_all(b"\n")?;
+
+    password
+}
+
+/// Turns off echo, canonical mode and signals for a terminal, restoring the original settings when
+/// dropped.
+struct RawMode {
+    fd: libc::c_int,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(fd: libc::c_int) -> io::Result<Self> {
+        // SAFETY:
+        // `termios` is a plain C struct that is filled in by `tcgetattr`.
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        // SAFETY:
+        // `original` is a valid `termios` struct.
+        if unsafe { libc::tcgetattr(fd, &raw mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        // Signals are turned off so that Ctrl-C is read and the settings can be restored.
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // SAFETY:
+        // `raw` is a valid `termios` struct.
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &raw const raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY:
+        // `original` holds the settings read by `tcgetattr`.
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSAFLUSH, &raw const self.original);
+        }
+    }
+}
+
+/// Reads a single byte into `byte` without any buffering, returning `false` at the end of the
+/// input.
+fn read_byte(fd: libc::c_int, byte: &mut u8) -> io::Result<bool> {
+    loop {
+        // SAFETY:
+        // `byte` is valid for writing a single byte.
+        match unsafe { libc::read(fd, (&raw mut *byte).cast(), 1) } {
+            1 => return Ok(true),
+            0 => return Ok(false),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+/// Reads a password from `fd` one byte at a time. Control characters are only handled if
+/// `terminal` is `true`.
+fn read_password<B: Backend>(fd: libc::c_int, terminal: bool) -> io::Result<Encrusted<String, B>> {
+    let mut builder = EncrustedStringBuilder::<B>::with_random_key();
+    // The length of each character, used to remove the last character on backspace.
+    let mut char_lens = Vec::new();
+    // The bytes of the character currently being read.
+    let mut pending = Zeroizing::new([0u8; 4]);
+    let mut pending_len = 0;
+
+    while read_byte(fd, &mut pending[pending_len])? {
+        match pending[pending_len] {
+            b'\n' | b'\r' => break,
+            CTRL_D if terminal => break,
+            CTRL_C if terminal => return Err(io::ErrorKind::Interrupted.into()),
+            BACKSPACE | DELETE if terminal => {
+                if let Some(len) = char_lens.pop() {
+                    builder.truncate(builder.len() - len);
+                }
+            }
+            CTRL_U if terminal => {
+                char_lens.clear();
+                builder.truncate(0);
+            }
+            _ => {
+                pending_len += 1;
+
+                match std::str::from_utf8(&pending[..pending_len]) {
+                    Ok(ch) => {
+                        builder.append(ch);
+                        char_lens.push(pending_len);
+                        pending_len = 0;
+                    }
+                    // The character continues in the next byte.
+                    Err(error) if error.error_len().is_none() => {}
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    if pending_len > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Password is not valid UTF-8",
+        ));
+    }
+
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the read end of a pipe containing `input`.
+    fn pipe(input: &[u8]) -> libc::c_int {
+        let mut fds = [0; 2];
+
+        // SAFETY:
+        // `fds` is valid for writing two file descriptors, and `input` for reading its length.
+        unsafe {
+            assert_eq!(0, libc::pipe(fds.as_mut_ptr()));
+            assert_eq!(
+                isize::try_from(input.len()).unwrap(),
+                libc::write(fds[1], input.as_ptr().cast(), input.len())
+            );
+            libc::close(fds[1]);
+        }
+
+        fds[0]
+    }
+
+    fn read(input: &[u8], terminal: bool) -> io::Result<String> {
+        let fd = pipe(input);
+        let password = read_password::<SmallRngBackend>(fd, terminal);
+
+        // SAFETY:
+        // The file descriptor is not used after this.
+        unsafe {
+            libc::close(fd);
+        }
+
+        Ok(password?.decrust().clone())
+    }
+
+    #[test]
+    fn pipe_input() {
+        assert_eq!(
+            "A secret😊",
+            read(b"A secret\xf0\x9f\x98\x8a\nrest", false).unwrap()
+        );
+        assert_eq!("No line break", read(b"No line break", false).unwrap());
+        assert_eq!("", read(b"", false).unwrap());
+        assert_eq!("\x7f", read(b"\x7f", false).unwrap());
+    }
+
+    #[test]
+    fn keystrokes() {
+        assert_eq!(
+            "secret😊",
+            read(b"secrx\x7fet\xf0\x9f\x98\x8ax\x08\r", true).unwrap()
+        );
+        assert_eq!("new", read(b"old\x15new\x04ignored", true).unwrap());
+        assert_eq!("", read(b"\x7f\x7f\n", true).unwrap());
+        assert_eq!(
+            io::ErrorKind::Interrupted,
+            read(b"sec\x03ret\n", true).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            read(b"\xff\n", false).unwrap_err().kind()
+        );
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            read(b"\xf0\x9f", false).unwrap_err().kind()
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Key material supplied at runtime, keeping the actual key away from the obfuscated data.
+//!
+//! [`Encrusted`](crate::Encrusted) objects using the [`Provided`] backend only store a random
+//! nonce. Whenever the data is obfuscated or deobfuscated, the [`KeyProvider`] is asked for the
+//! key material, which is combined with the nonce to create the key for the underlying backend.
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::{marker::PhantomData, path::Path};
+
+use hmac::{Hmac, Mac, digest::KeyInit};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use crate::{Backend, SealableBackend, SeekableBackend, SmallRngBackend};
+
+/// Trait implemented by types supplying key material to the [`Provided`] backend.
+///
+/// # Example
+/// ```
+/// use encrust_core::{Encrusted, KeyProvider, Provided, combine_key};
+///
+/// struct ApiTokenKey;
+///
+/// impl KeyProvider for ApiTokenKey {
+///     fn provide_key(key: &mut [u8; 32]) {
+///         // Key material would typically come from outside of the executable, see
+///         // `key_from_env` and `key_from_file`.
+///         key.fill(0x42);
+///         combine_key(key, b"Compile-time constant 0123456789");
+///     }
+/// }
+///
+/// let mut api_token =
+///     Encrusted::<_, Provided<ApiTokenKey>>::with_key("A secret token".to_string(), 0xc0ffee);
+/// assert_eq!("A secret token", api_token.decrust().as_str());
+/// ```
+pub trait KeyProvider {
+    /// Fills `key` with key material. This is called every time data is obfuscated or
+    /// deobfuscated, and must fill `key` with the same bytes every time.
+    ///
+    /// # Panics
+    /// The data cannot be accessed without the key material, so implementations should panic if
+    /// the key material is not available.
+    fn provide_key(key: &mut [u8; 32]);
+}
+
+/// Backend using key material from the [`KeyProvider`] `P` to generate a key for the backend `B`.
+///
+/// The key stored in [`Encrusted`](crate::Encrusted) objects using this backend is a nonce that is
+/// combined with the provided key material, so that data obfuscated with different nonces uses
+/// different keys. The key of `B` is derived from the key material and the nonce using HKDF with
+/// HMAC-SHA256, so the key material cannot be recovered from a derived key. The derived key has the
+/// same size as the key of `B`, meaning that the keystream only depends on 64 bits derived from the
+/// key material when using [`SmallRngBackend`].
+pub struct Provided<P, B = SmallRngBackend>(PhantomData<(P, B)>);
+
+impl<P, B> Backend for Provided<P, B>
+where
+    P: KeyProvider,
+    B: Backend,
+{
+    type Key = u64;
+    type Keystream = B::Keystream;
+
+    const VERSION: u8 = B::VERSION;
+    const KEY_SIZE: usize = 8;
+
+    fn keystream(key: &Self::Key) -> Self::Keystream {
+        let mut backend_key = Self::backend_key(*key);
+        let keystream = B::keystream(&backend_key);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn versioned_keystream(key: &Self::Key, version: u8) -> Option<Self::Keystream> {
+        let mut backend_key = Self::backend_key(*key);
+        let keystream = B::versioned_keystream(&backend_key, version);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn mac_keystream(key: &Self::Key) -> Self::Keystream {
+        let mut backend_key = Self::backend_key(*key);
+        let keystream = B::mac_keystream(&backend_key);
+        backend_key.zeroize();
+
+        keystream
+    }
+
+    fn key_from_material(material: &[u8]) -> Self::Key {
+        SmallRngBackend::key_from_material(material)
+    }
+}
+
+impl<P, B> Provided<P, B>
+where
+    P: KeyProvider,
+    B: Backend,
+{
+    /// Derives the key of `B` from the provided key material and `nonce`.
+    fn backend_key(nonce: u64) -> B::Key {
+        let mut material = [0; 32];
+        P::provide_key(&mut material);
+
+        let backend_key = derive_key::<B>(&material, b"encrust:provided", nonce);
+        material.zeroize();
+
+        backend_key
+    }
+}
+
+impl<P, B> SeekableBackend for Provided<P, B>
+where
+    P: KeyProvider,
+    B: SeekableBackend,
+{
+    fn seek(keystream: &mut Self::Keystream, block: u64) {
+        B::seek(keystream, block);
+    }
+}
+
+/// Only the nonce is stored in sealed blobs, so the same key material must be provided when they
+/// are opened. The identifier of the backend `B` is combined with `0x8000` to identify the backend.
+impl<P, B> SealableBackend for Provided<P, B>
+where
+    P: KeyProvider,
+    B: SealableBackend,
+{
+    const ALGORITHM: u16 = 0x8000 | B::ALGORITHM;
+
+    fn key_to_bytes(key: &Self::Key) -> Vec<u8> {
+        key.to_le_bytes().to_vec()
+    }
+
+    fn key_from_bytes(bytes: &[u8]) -> Option<Self::Key> {
+        Some(Self::Key::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// Derives a key for the backend `B` from 32 bytes of key material using HKDF with HMAC-SHA256.
+/// `label` and `nonce` are part of the HKDF info, so keys derived for different labels and nonces
+/// are independent, and neither the key material nor other keys can be recovered from a key.
+pub(crate) fn derive_key<B>(material: &[u8; 32], label: &[u8], nonce: u64) -> B::Key
+where
+    B: Backend,
+{
+    assert!(
+        B::KEY_SIZE <= 255 * 32,
+        "HKDF cannot derive keys larger than 8160 bytes"
+    );
+
+    // HKDF-Extract, as key material from a `KeyProvider` is not necessarily uniformly random.
+    let mut prk = hmac_sha256(b"encrust", &[material]);
+
+    // HKDF-Expand, filling the key material of `B` directly.
+    let mut okm = vec![0; B::KEY_SIZE];
+    let mut block = [0; 32];
+    for (counter, chunk) in (1u8..).zip(okm.chunks_mut(32)) {
+        let previous = if counter == 1 { &[][..] } else { &block[..] };
+        let mut next = hmac_sha256(&prk, &[previous, label, &nonce.to_le_bytes(), &[counter]]);
+        block.copy_from_slice(&next);
+        next.zeroize();
+
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    prk.zeroize();
+    block.zeroize();
+
+    let key = B::key_from_material(&okm);
+    okm.zeroize();
+
+    key
+}
+
+/// Calculates HMAC-SHA256 of the concatenation of `data` using `key`.
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let mut mac =
+        <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+/// Combines `key` with `value` using XOR. Can be used by [`KeyProvider`] implementations to
+/// combine key material supplied at runtime with a constant included in the executable.
+pub fn combine_key(key: &mut [u8; 32], value: &[u8; 32]) {
+    for (key_byte, value_byte) in key.iter_mut().zip(value) {
+        *key_byte ^= value_byte;
+    }
+}
+
+/// Reads key material for a [`KeyProvider`] from the environment variable `name`, which must
+/// contain 64 hexadecimal digits.
+///
+/// # Panics
+/// Panics if the environment variable is not set or does not contain exactly 64 hexadecimal digits.
+#[cfg(feature = "std")]
+pub fn key_from_env(name: &str, key: &mut [u8; 32]) {
+    let Ok(mut value) = std::env::var(name) else {
+        panic!("Environment variable {name} containing key material is not set");
+    };
+
+    let decoded = decode_hex(value.as_bytes(), key);
+    value.zeroize();
+
+    assert!(
+        decoded,
+        "Environment variable {name} must contain 64 hexadecimal digits"
+    );
+}
+
+/// Reads key material for a [`KeyProvider`] from the file at `path`, which must contain exactly 32
+/// bytes.
+///
+/// # Panics
+/// Panics if the file cannot be read or does not contain exactly 32 bytes.
+#[cfg(feature = "std")]
+pub fn key_from_file(path: impl AsRef<Path>, key: &mut [u8; 32]) {
+    let path = path.as_ref();
+    let mut contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) => panic!("Unable to read key material from {}: {err}", path.display()),
+    };
+
+    let correct_length = contents.len() == key.len();
+    if correct_length {
+        key.copy_from_slice(&contents);
+    }
+    contents.zeroize();
+
+    assert!(
+        correct_length,
+        "{} must contain exactly 32 bytes of key material",
+        path.display()
+    );
+}
+
+/// Decodes 64 hexadecimal digits into `key`, returning `false` if `hex` is not valid.
+#[cfg(feature = "std")]
+fn decode_hex(hex: &[u8], key: &mut [u8; 32]) -> bool {
+    const fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    if hex.len() != key.len() * 2 {
+        return false;
+    }
+
+    for (byte, pair) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        let (Some(high), Some(low)) = (digit(pair[0]), digit(pair[1])) else {
+            key.zeroize();
+            return false;
+        };
+
+        *byte = (high << 4) | low;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Encrusted;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog";
+
+    struct FirstKey;
+
+    impl KeyProvider for FirstKey {
+        fn provide_key(key: &mut [u8; 32]) {
+            key.copy_from_slice(b"First key material, 32 bytes....");
+        }
+    }
+
+    struct SecondKey;
+
+    impl KeyProvider for SecondKey {
+        fn provide_key(key: &mut [u8; 32]) {
+            key.copy_from_slice(b"Other key material, 32 bytes....");
+        }
+    }
+
+    #[test]
+    fn provided_key() {
+        let mut first = Encrusted::<_, Provided<FirstKey>>::with_key(TEST_STRING.to_string(), 1);
+        let mut second = Encrusted::<_, Provided<SecondKey>>::with_key(TEST_STRING.to_string(), 1);
+        let mut other_nonce =
+            Encrusted::<_, Provided<FirstKey>>::with_key(TEST_STRING.to_string(), 2);
+
+        assert_ne!(TEST_STRING.as_bytes(), first.data.as_bytes());
+        assert_ne!(first.data.as_bytes(), second.data.as_bytes());
+        assert_ne!(first.data.as_bytes(), other_nonce.data.as_bytes());
+
+        assert_eq!(TEST_STRING, first.decrust().as_str());
+        assert_eq!(TEST_STRING, second.decrust().as_str());
+        assert_eq!(TEST_STRING, other_nonce.decrust().as_str());
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn provided_chacha20_key() {
+        use crate::ChaCha20Backend;
+
+        let mut encrusted = Encrusted::<_, Provided<FirstKey, ChaCha20Backend>>::with_key(
+            TEST_STRING.to_string(),
+            1,
+        );
+
+        assert_ne!(TEST_STRING.as_bytes(), encrusted.data.as_bytes());
+        assert_eq!(TEST_STRING, encrusted.decrust().as_str());
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn derived_keys() {
+        use crate::ChaCha20Backend;
+
+        let material = [0x42; 32];
+        let key = derive_key::<ChaCha20Backend>(&material, b"encrust:provided", 1);
+
+        assert_ne!(material, key);
+        assert_eq!(
+            key,
+            derive_key::<ChaCha20Backend>(&material, b"encrust:provided", 1)
+        );
+        assert_ne!(
+            key,
+            derive_key::<ChaCha20Backend>(&material, b"encrust:provided", 2)
+        );
+        assert_ne!(
+            key,
+            derive_key::<ChaCha20Backend>(&material, b"encrust:keyring", 1)
+        );
+        assert_ne!(
+            key,
+            derive_key::<ChaCha20Backend>(&[0x43; 32], b"encrust:provided", 1)
+        );
+    }
+
+    #[test]
+    fn combine() {
+        let mut key = [0b1010; 32];
+        combine_key(&mut key, &[0b0110; 32]);
+
+        assert_eq!([0b1100; 32], key);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hex() {
+        let mut key = [0; 32];
+
+        assert!(decode_hex(
+            b"000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F",
+            &mut key
+        ));
+        assert_eq!(
+            core::array::from_fn::<u8, 32, _>(|i| u8::try_from(i).unwrap()),
+            key
+        );
+
+        assert!(!decode_hex(b"0001", &mut key));
+        assert!(!decode_hex(
+            b"g00102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            &mut key
+        ));
+        assert_eq!([0; 32], key);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("encrust-key-{}", std::process::id()));
+        std::fs::write(&path, [0x42; 32]).unwrap();
+
+        let mut key = [0; 32];
+        key_from_file(&path, &mut key);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!([0x42; 32], key);
+    }
+}
@@ -0,0 +1,193 @@
+//! Streaming obfuscated byte buffers without deobfuscating all of the data at once.
+
+use std::io::{self, BufRead, Read};
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{Backend, Bytes, Encrusted, Keystream};
+
+/// Number of bytes deobfuscated at a time, matching the blocks of keystream used to obfuscate
+/// [`Bytes`] and `Vec<u8>`.
+const BLOCK_SIZE: usize = 64;
+
+/// Reader deobfuscating an [`EncrustedBytes`](crate::EncrustedBytes) or `Encrusted<Vec<u8>>` buffer
+/// one block of 64 bytes at a time. Create it using [`Encrusted::reader`].
+///
+/// Only the current block is deobfuscated, into a buffer that is zeroized when the next block is
+/// read and when the reader is dropped. The obfuscated data is left untouched, so several readers
+/// can read the same buffer at the same time. The integrity of authenticated data is not verified.
+///
+/// # Example
+/// ```
+/// use std::io::Read;
+///
+/// use encrust_core::{Bytes, EncrustedBytes};
+///
+/// let certificate = EncrustedBytes::new(Bytes::from(b"A secret certificate".to_vec()), 0xc0ffee);
+///
+/// let mut start = [0; 8];
+/// certificate.reader().read_exact(&mut start).unwrap();
+/// assert_eq!(b"A secret", &start);
+/// ```
+pub struct EncrustedReader<'a, B>
+where
+    B: Backend,
+{
+    data: &'a [u8],
+    keystream: B::Keystream,
+    /// Position of the next byte to read.
+    position: usize,
+    /// End of the block that has been deobfuscated into `plain`.
+    block_end: usize,
+    plain: Zeroizing<[u8; BLOCK_SIZE]>,
+}
+
+impl<'a, B> EncrustedReader<'a, B>
+where
+    B: Backend,
+{
+    fn new(data: &'a [u8], key: &B::Key) -> Self {
+        Self {
+            data,
+            keystream: B::keystream(key),
+            position: 0,
+            block_end: 0,
+            plain: Zeroizing::new([0; BLOCK_SIZE]),
+        }
+    }
+}
+
+impl<B> BufRead for EncrustedReader<'_, B>
+where
+    B: Backend,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // The blocks of keystream are generated in order, so the next block is only deobfuscated
+        // once the current one has been consumed.
+        if self.position == self.block_end && self.position < self.data.len() {
+            self.block_end = (self.position + BLOCK_SIZE).min(self.data.len());
+            self.keystream.fill(&mut *self.plain);
+
+            let block = &self.data[self.position..self.block_end];
+            for (byte, obfuscated) in self.plain.iter_mut().zip(block) {
+                *byte ^= obfuscated;
+            }
+            self.plain[block.len()..].zeroize();
+        }
+
+        let offset = self.position % BLOCK_SIZE;
+        Ok(&self.plain[offset..offset + self.block_end - self.position])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.block_end);
+    }
+}
+
+impl<B> Read for EncrustedReader<'_, B>
+where
+    B: Backend,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            let available = self.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+
+            let amount = available.len().min(buf.len() - read);
+            buf[read..read + amount].copy_from_slice(&available[..amount]);
+            self.consume(amount);
+            read += amount;
+        }
+
+        Ok(read)
+    }
+}
+
+impl<B> Encrusted<Bytes, B>
+where
+    B: Backend,
+{
+    /// Returns a reader deobfuscating the data one block at a time, leaving the stored data
+    /// obfuscated. See [`EncrustedReader`].
+    pub fn reader(&self) -> EncrustedReader<'_, B> {
+        EncrustedReader::new(&self.data, &self.key)
+    }
+}
+
+impl<B> Encrusted<Vec<u8>, B>
+where
+    B: Backend,
+{
+    /// Returns a reader deobfuscating the data one block at a time, leaving the stored data
+    /// obfuscated. See [`EncrustedReader`].
+    pub fn reader(&self) -> EncrustedReader<'_, B> {
+        EncrustedReader::new(&self.data, &self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EncrustedBytes;
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[test]
+    fn read_to_end() {
+        let data = TEST_STRING.repeat(10).into_bytes();
+        let bytes = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+        let vec = Encrusted::new(data.clone(), 0x2357_bd11);
+
+        let mut read = Vec::new();
+        bytes.reader().read_to_end(&mut read).unwrap();
+        assert_eq!(data, read);
+
+        read.clear();
+        vec.reader().read_to_end(&mut read).unwrap();
+        assert_eq!(data, read);
+    }
+
+    #[test]
+    fn small_reads() {
+        let data = TEST_STRING.repeat(10).into_bytes();
+        let bytes = EncrustedBytes::new(Bytes::from(data.clone()), 0x2357_bd11);
+        let mut reader = bytes.reader();
+
+        let mut read = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&buf[..n]);
+        }
+
+        assert_eq!(data, read);
+    }
+
+    #[test]
+    fn buf_read() {
+        let data = format!("{TEST_STRING}\n").repeat(5);
+        let bytes = EncrustedBytes::new(Bytes::from(data.as_bytes()), 0x2357_bd11);
+
+        let lines = bytes
+            .reader()
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(vec![TEST_STRING; 5], lines);
+    }
+
+    #[test]
+    fn empty() {
+        let bytes = EncrustedBytes::new(Bytes::default(), 0x2357_bd11);
+
+        let mut read = Vec::new();
+        assert_eq!(0, bytes.reader().read_to_end(&mut read).unwrap());
+    }
+}
@@ -0,0 +1,400 @@
+//! Sealed blobs, a self-describing binary format for persisting [`Encrusted`] objects.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fmt;
+
+use zeroize::Zeroize;
+
+#[cfg(feature = "integrity")]
+use crate::{Authenticate, IntegrityError, encode::decode_all, integrity::Integrity};
+use crate::{
+    EncodeObfuscated, Encrustable, Encrusted, SealableBackend, backend::supports_version,
+    encode::take,
+};
+
+/// The magic bytes at the start of every sealed blob.
+pub const SEALED_MAGIC: [u8; 8] = *b"ENCRUST\0";
+
+/// The version of the sealed blob format.
+pub const SEALED_FORMAT_VERSION: u8 = 1;
+
+/// Flag set if the blob holds an integrity tag.
+const FLAG_TAG: u8 = 1;
+
+/// Error returned when opening a sealed blob fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SealedError {
+    /// The blob does not start with [`SEALED_MAGIC`], or is not a valid sealed blob.
+    InvalidFormat,
+    /// The blob uses a version of the format that is not supported.
+    UnsupportedVersion(u8),
+    /// The blob was sealed using a different backend.
+    AlgorithmMismatch,
+    /// The blob was sealed using a version of the backend's algorithm that is not supported.
+    UnsupportedAlgorithmVersion(u8),
+    /// The blob could not be verified using its integrity tag.
+    #[cfg(feature = "integrity")]
+    Integrity(IntegrityError),
+}
+
+impl fmt::Display for SealedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "invalid sealed blob"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported sealed blob version {version}")
+            }
+            Self::AlgorithmMismatch => write!(f, "sealed blob uses a different backend"),
+            Self::UnsupportedAlgorithmVersion(version) => {
+                write!(f, "unsupported algorithm version {version}")
+            }
+            #[cfg(feature = "integrity")]
+            Self::Integrity(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl core::error::Error for SealedError {}
+
+/// The parsed fields of a sealed blob.
+struct Blob<'blob, K>
+where
+    K: Zeroize,
+{
+    key: K,
+    algorithm_version: u8,
+    payload: &'blob [u8],
+    #[cfg_attr(
+        not(feature = "integrity"),
+        expect(
+            dead_code,
+            reason = "Tags are only verified with the integrity feature"
+        )
+    )]
+    tag: Option<[u8; 32]>,
+}
+
+/// Parses `bytes` as a sealed blob sealed using the backend `B`.
+fn parse<B>(bytes: &[u8]) -> Result<Blob<'_, B::Key>, SealedError>
+where
+    B: SealableBackend,
+{
+    let mut input = bytes;
+
+    if take(&mut input, SEALED_MAGIC.len()) != Some(&SEALED_MAGIC) {
+        return Err(SealedError::InvalidFormat);
+    }
+
+    let version = u8::decode(&mut input).ok_or(SealedError::InvalidFormat)?;
+    if version != SEALED_FORMAT_VERSION {
+        return Err(SealedError::UnsupportedVersion(version));
+    }
+
+    let algorithm = u16::decode(&mut input).ok_or(SealedError::InvalidFormat)?;
+    if algorithm != B::ALGORITHM {
+        return Err(SealedError::AlgorithmMismatch);
+    }
+
+    let algorithm_version = u8::decode(&mut input).ok_or(SealedError::InvalidFormat)?;
+
+    let flags = u8::decode(&mut input).ok_or(SealedError::InvalidFormat)?;
+    if flags & !FLAG_TAG != 0 {
+        return Err(SealedError::InvalidFormat);
+    }
+
+    let key_len = u16::decode(&mut input).ok_or(SealedError::InvalidFormat)?;
+    let mut key = take(&mut input, key_len.into())
+        .and_then(B::key_from_bytes)
+        .ok_or(SealedError::InvalidFormat)?;
+
+    if !supports_version::<B>(&key, algorithm_version) {
+        key.zeroize();
+        return Err(SealedError::UnsupportedAlgorithmVersion(algorithm_version));
+    }
+
+    let payload = u64::decode(&mut input)
+        .and_then(|len| usize::try_from(len).ok())
+        .and_then(|len| take(&mut input, len));
+    let tag = if flags & FLAG_TAG == 0 {
+        Some(None)
+    } else {
+        take(&mut input, 32).map(|tag| tag.try_into().ok())
+    };
+
+    match (payload, tag) {
+        (Some(payload), Some(tag)) if input.is_empty() => Ok(Blob {
+            key,
+            algorithm_version,
+            payload,
+            tag,
+        }),
+        _ => {
+            key.zeroize();
+            Err(SealedError::InvalidFormat)
+        }
+    }
+}
+
+impl<T, B> Encrusted<T, B>
+where
+    T: Encrustable + EncodeObfuscated + Zeroize,
+    B: SealableBackend,
+{
+    /// Returns a sealed blob holding the obfuscated data, its key and the integrity tag of
+    /// authenticated objects. The data is not deobfuscated.
+    ///
+    /// Blobs hold the key next to the obfuscated data, like `Encrusted` objects in memory, so they
+    /// are only as secret as the backend makes them. Backends such as [`Provided`](crate::Provided)
+    /// store a nonce referring to key material supplied at runtime instead of the key itself.
+    ///
+    /// # Format
+    /// All numbers are stored in little-endian byte order.
+    ///
+    /// | Size           | Field                                                               |
+    /// |----------------|---------------------------------------------------------------------|
+    /// | 8              | The magic bytes [`SEALED_MAGIC`].                                   |
+    /// | 1              | The version of the format, currently [`SEALED_FORMAT_VERSION`].     |
+    /// | 2              | The identifier of the backend, [`SealableBackend::ALGORITHM`].      |
+    /// | 1              | The version of the backend's algorithm, [`Backend::VERSION`].       |
+    /// | 1              | Flags. Bit 0 is set if the blob holds an integrity tag.             |
+    /// | 2              | The length of the key.                                              |
+    /// | Key length     | The key, as returned by [`SealableBackend::key_to_bytes`].          |
+    /// | 8              | The length of the payload.                                          |
+    /// | Payload length | The obfuscated data, encoded by [`EncodeObfuscated`].               |
+    /// | 32             | The HMAC-SHA256 tag of the obfuscated data, if bit 0 of the flags is set. |
+    ///
+    /// # Panics
+    /// Panics if the key returned by [`SealableBackend::key_to_bytes`] is longer than
+    /// `u16::MAX` bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use encrust_core::Encrusted;
+    ///
+    /// let api_token = Encrusted::new("A secret token".to_string(), 0xc0ffee);
+    /// let sealed = api_token.to_sealed_bytes();
+    ///
+    /// let mut api_token = Encrusted::<String>::from_sealed_bytes(&sealed).unwrap();
+    /// assert_eq!("A secret token", api_token.decrust().as_str());
+    /// ```
+    ///
+    /// [`Backend::VERSION`]: crate::Backend::VERSION
+    pub fn to_sealed_bytes(&self) -> Vec<u8> {
+        #[cfg(feature = "integrity")]
+        let tag = self.integrity.as_ref().map(Integrity::tag);
+        #[cfg(not(feature = "integrity"))]
+        let tag: Option<&[u8; 32]> = None;
+
+        let mut key = B::key_to_bytes(&self.key);
+        let key_len =
+            u16::try_from(key.len()).expect("Keys longer than u16::MAX are not supported");
+        let payload = self.encode_data();
+
+        let mut sealed = Vec::new();
+        sealed.extend_from_slice(&SEALED_MAGIC);
+        SEALED_FORMAT_VERSION.encode(&mut sealed);
+        B::ALGORITHM.encode(&mut sealed);
+        B::VERSION.encode(&mut sealed);
+        u8::from(tag.is_some()).encode(&mut sealed);
+        key_len.encode(&mut sealed);
+        sealed.extend_from_slice(&key);
+        payload.len().encode(&mut sealed);
+        sealed.extend_from_slice(&payload);
+        if let Some(tag) = tag {
+            sealed.extend_from_slice(tag);
+        }
+
+        key.zeroize();
+
+        sealed
+    }
+
+    /// Opens a sealed blob created by [`Encrusted::to_sealed_bytes`] using the same backend,
+    /// without deobfuscating the data. Types that can hold invalid data while obfuscated, such as
+    /// strings, are deobfuscated in place to check that the data is valid.
+    ///
+    /// Blobs sealed using an older version of the backend's algorithm are deobfuscated using that
+    /// version and obfuscated again using the current version.
+    ///
+    /// The integrity tag of the blob is not verified, and the returned object is not
+    /// authenticated. Use `Encrusted::from_sealed_bytes_authenticated` to verify the tag.
+    ///
+    /// # Errors
+    /// Returns a [`SealedError`] if `bytes` is not a valid sealed blob, uses an unsupported version
+    /// of the format or the algorithm, or was sealed using a different backend.
+    pub fn from_sealed_bytes(bytes: &[u8]) -> Result<Self, SealedError> {
+        let blob = parse::<B>(bytes)?;
+
+        Self::from_encoded_data(blob.payload, blob.key, blob.algorithm_version)
+            .ok_or(SealedError::InvalidFormat)
+    }
+
+    /// Works like [`Encrusted::from_sealed_bytes`], but requires the blob to hold an integrity tag
+    /// and verifies it. The returned object is authenticated.
+    ///
+    /// Tags are calculated over the obfuscated data, so blobs sealed using an older version of the
+    /// backend's algorithm do not match their tags.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`Encrusted::from_sealed_bytes`], and
+    /// [`SealedError::Integrity`] if the blob does not hold a tag or the data does not match it.
+    #[cfg(feature = "integrity")]
+    pub fn from_sealed_bytes_authenticated(bytes: &[u8]) -> Result<Self, SealedError>
+    where
+        T: Authenticate,
+    {
+        let mut blob = parse::<B>(bytes)?;
+        let Some(tag) = blob.tag else {
+            blob.key.zeroize();
+            return Err(SealedError::Integrity(IntegrityError::NotAuthenticated));
+        };
+
+        let Some(decoded) = decode_all(blob.payload) else {
+            blob.key.zeroize();
+            return Err(SealedError::InvalidFormat);
+        };
+
+        // The data is verified before it is deobfuscated to check that it is valid.
+        let encrusted =
+            Self::from_versioned_authenticated(decoded, blob.key, tag, blob.algorithm_version)
+                .ok_or(SealedError::InvalidFormat)?;
+        let verified = encrusted
+            .integrity
+            .as_ref()
+            .is_some_and(|integrity| integrity.verify(&encrusted.data, &encrusted.key));
+        if !verified {
+            return Err(SealedError::Integrity(IntegrityError::Mismatch));
+        }
+
+        encrusted.check_decoded().ok_or(SealedError::InvalidFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, Bytes, EncrustedBytes, SmallRngBackend};
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    #[test]
+    fn round_trips() {
+        let sealed = Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11).to_sealed_bytes();
+        let mut string = Encrusted::<String>::from_sealed_bytes(&sealed).unwrap();
+        assert_eq!(TEST_STRING, string.decrust().as_str());
+
+        let sealed = Encrusted::new(vec![[1u64, 2], [3, u64::MAX]], 0x2357_bd11).to_sealed_bytes();
+        let mut numbers = Encrusted::<Vec<[u64; 2]>>::from_sealed_bytes(&sealed).unwrap();
+        assert_eq!(vec![[1, 2], [3, u64::MAX]], *numbers.decrust());
+
+        let bytes = EncrustedBytes::new(Bytes::from(TEST_STRING.as_bytes()), 0x2357_bd11);
+        let mut bytes =
+            EncrustedBytes::<SmallRngBackend>::from_sealed_bytes(&bytes.to_sealed_bytes()).unwrap();
+        assert_eq!(TEST_STRING.as_bytes(), bytes.decrust().as_slice());
+    }
+
+    #[test]
+    fn header() {
+        let sealed = Encrusted::new(1337u32, 0x2357_bd11).to_sealed_bytes();
+
+        let mut expected = SEALED_MAGIC.to_vec();
+        expected.push(SEALED_FORMAT_VERSION);
+        expected.extend_from_slice(&1u16.to_le_bytes());
+        expected.push(SmallRngBackend::VERSION);
+        expected.push(0);
+        expected.extend_from_slice(&8u16.to_le_bytes());
+        expected.extend_from_slice(&0x2357_bd11u64.to_le_bytes());
+        expected.extend_from_slice(&4u64.to_le_bytes());
+
+        assert_eq!(expected.len() + 4, sealed.len());
+        assert_eq!(expected, sealed[..expected.len()]);
+        assert_ne!(1337u32.to_le_bytes(), sealed[expected.len()..]);
+    }
+
+    #[test]
+    fn invalid_blobs() {
+        let sealed = Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11).to_sealed_bytes();
+        let open = |bytes: &[u8]| Encrusted::<String>::from_sealed_bytes(bytes).err();
+
+        assert_eq!(Some(SealedError::InvalidFormat), open(&sealed[1..]));
+        assert_eq!(
+            Some(SealedError::InvalidFormat),
+            open(&sealed[..sealed.len() - 1])
+        );
+        assert_eq!(
+            Some(SealedError::InvalidFormat),
+            open(&[sealed.clone(), vec![0]].concat())
+        );
+
+        let mut version = sealed.clone();
+        version[8] = 2;
+        assert_eq!(Some(SealedError::UnsupportedVersion(2)), open(&version));
+
+        let mut algorithm = sealed.clone();
+        algorithm[9] = 2;
+        assert_eq!(Some(SealedError::AlgorithmMismatch), open(&algorithm));
+
+        let mut algorithm_version = sealed.clone();
+        algorithm_version[11] = 0;
+        assert_eq!(
+            Some(SealedError::UnsupportedAlgorithmVersion(0)),
+            open(&algorithm_version)
+        );
+
+        let mut flags = sealed.clone();
+        flags[12] = 2;
+        assert_eq!(Some(SealedError::InvalidFormat), open(&flags));
+
+        // Flipping the highest bit of the first obfuscated character makes it an invalid UTF-8
+        // sequence once deobfuscated. The characters start after 31 bytes of header, key and
+        // payload length, and 8 bytes encoding the length of the string.
+        let mut invalid_utf8 = sealed;
+        invalid_utf8[39] ^= 0x80;
+        assert_eq!(Some(SealedError::InvalidFormat), open(&invalid_utf8));
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn authenticated() {
+        let sealed =
+            Encrusted::new_authenticated(TEST_STRING.to_string(), 0x2357_bd11).to_sealed_bytes();
+
+        let mut opened = Encrusted::<String>::from_sealed_bytes_authenticated(&sealed).unwrap();
+        assert!(opened.is_authenticated());
+        assert_eq!(TEST_STRING, opened.try_decrust().unwrap().as_str());
+
+        let mut tampered = sealed;
+        tampered[40] ^= 1;
+        assert_eq!(
+            Some(SealedError::Integrity(IntegrityError::Mismatch)),
+            Encrusted::<String>::from_sealed_bytes_authenticated(&tampered).err()
+        );
+
+        let unauthenticated =
+            Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11).to_sealed_bytes();
+        assert_eq!(
+            Some(SealedError::Integrity(IntegrityError::NotAuthenticated)),
+            Encrusted::<String>::from_sealed_bytes_authenticated(&unauthenticated).err()
+        );
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn chacha20() {
+        use crate::ChaCha20Backend;
+
+        let sealed = Encrusted::<_, ChaCha20Backend>::with_key(TEST_STRING.to_string(), [0x42; 32])
+            .to_sealed_bytes();
+
+        let mut opened = Encrusted::<String, ChaCha20Backend>::from_sealed_bytes(&sealed).unwrap();
+        assert_eq!(TEST_STRING, opened.decrust().as_str());
+        assert_eq!(
+            Some(SealedError::AlgorithmMismatch),
+            Encrusted::<String>::from_sealed_bytes(&sealed).err()
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Serializing [`Encrusted`] objects in their obfuscated form using `serde`.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fmt;
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, SeqAccess, Visitor},
+};
+use zeroize::Zeroize;
+
+use crate::{Backend, EncodeObfuscated, Encrustable, Encrusted, backend::supports_version};
+
+/// The version of the format used to serialize [`Encrusted`] objects using `serde`.
+///
+/// `Encrusted` objects are serialized as a struct named `Encrusted` with four fields:
+///
+/// * `version`: The version of the format as a `u8`. Deserializing data with any other version
+///   fails.
+/// * `algorithm_version`: The version of the backend's algorithm used to obfuscate the data, see
+///   [`Backend::VERSION`]. Data obfuscated using an older version is obfuscated again using the
+///   current version when it is deserialized.
+/// * `key`: The key used by the backend, such as the seed of [`SmallRngBackend`] or the nonce of
+///   [`Provided`]. Only backends whose keys implement `Serialize` and `Deserialize` are supported.
+/// * `data`: The obfuscated data as bytes, encoded by [`EncodeObfuscated`].
+///
+/// Integrity tags and key rotation settings are not serialized, so deserialized objects are never
+/// authenticated and do not rotate their keys.
+///
+/// [`SmallRngBackend`]: crate::SmallRngBackend
+/// [`Provided`]: crate::Provided
+pub const SERDE_FORMAT_VERSION: u8 = 1;
+
+/// Encoded obfuscated data, serialized as bytes.
+struct EncodedData(Vec<u8>);
+
+impl Serialize for EncodedData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for EncodedData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EncodedDataVisitor;
+
+        impl<'de> Visitor<'de> for EncodedDataVisitor {
+            type Value = EncodedData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("encoded obfuscated data")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(EncodedData(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(EncodedData(v))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                while let Some(byte) = seq.next_element()? {
+                    data.push(byte);
+                }
+
+                Ok(EncodedData(data))
+            }
+        }
+
+        deserializer.deserialize_bytes(EncodedDataVisitor)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Encrusted")]
+struct SerializedRef<'encrusted, K> {
+    version: u8,
+    algorithm_version: u8,
+    key: &'encrusted K,
+    data: EncodedData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Encrusted")]
+struct Serialized<K> {
+    version: u8,
+    algorithm_version: u8,
+    key: K,
+    data: EncodedData,
+}
+
+/// Serializes the obfuscated data together with its key, without deobfuscating it.
+///
+/// # Example
+/// ```
+/// use encrust_core::Encrusted;
+///
+/// let api_token = Encrusted::new("A secret token".to_string(), 0xc0ffee);
+/// let json = serde_json::to_string(&api_token).unwrap();
+/// assert!(!json.contains("secret"));
+///
+/// let mut api_token: Encrusted<String> = serde_json::from_str(&json).unwrap();
+/// assert_eq!("A secret token", api_token.decrust().as_str());
+/// ```
+impl<T, B> Serialize for Encrusted<T, B>
+where
+    T: Encrustable + EncodeObfuscated + Zeroize,
+    B: Backend,
+    B::Key: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        SerializedRef {
+            version: SERDE_FORMAT_VERSION,
+            algorithm_version: B::VERSION,
+            key: &self.key,
+            data: EncodedData(self.encode_data()),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes data serialized by `Encrusted`'s `Serialize` implementation, without creating a
+/// deobfuscated copy of the data.
+///
+/// Types that can hold invalid data while obfuscated, such as strings, are deobfuscated in place
+/// to check that the data is valid before the object is returned.
+impl<'de, T, B> Deserialize<'de> for Encrusted<T, B>
+where
+    T: Encrustable + EncodeObfuscated + Zeroize,
+    B: Backend,
+    B::Key: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let Serialized {
+            version,
+            algorithm_version,
+            mut key,
+            data,
+        } = Serialized::<B::Key>::deserialize(deserializer)?;
+
+        if version != SERDE_FORMAT_VERSION {
+            key.zeroize();
+            return Err(de::Error::custom(format_args!(
+                "unsupported encrusted format version {version}"
+            )));
+        }
+
+        if !supports_version::<B>(&key, algorithm_version) {
+            key.zeroize();
+            return Err(de::Error::custom(format_args!(
+                "unsupported algorithm version {algorithm_version}"
+            )));
+        }
+
+        Self::from_encoded_data(&data.0, key, algorithm_version)
+            .ok_or_else(|| de::Error::custom("invalid encrusted data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Bytes, EncrustedBytes};
+
+    const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+    fn round_trip<T>(encrusted: &Encrusted<T>) -> Encrusted<T>
+    where
+        T: Encrustable + EncodeObfuscated + Zeroize,
+    {
+        serde_json::from_str(&serde_json::to_string(encrusted).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut string = round_trip(&Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11));
+        assert_eq!(TEST_STRING, string.decrust().as_str());
+
+        let mut numbers = round_trip(&Encrusted::new(vec![1u32, 2, 3, u32::MAX], 0x2357_bd11));
+        assert_eq!(vec![1, 2, 3, u32::MAX], *numbers.decrust());
+
+        let mut array = round_trip(&Encrusted::new([-1isize, 0, 1], 0x2357_bd11));
+        assert_eq!([-1, 0, 1], *array.decrust());
+
+        let mut strings = round_trip(&Encrusted::new(
+            [TEST_STRING.to_string(), String::new()],
+            0x2357_bd11,
+        ));
+        assert_eq!([TEST_STRING.to_string(), String::new()], *strings.decrust());
+
+        let bytes = EncrustedBytes::new(Bytes::from(TEST_STRING.as_bytes()), 0x2357_bd11);
+        let mut bytes = round_trip(&bytes);
+        assert_eq!(TEST_STRING.as_bytes(), bytes.decrust().as_slice());
+    }
+
+    #[test]
+    fn serialized_form() {
+        let encrusted = Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11);
+        let value = serde_json::to_value(&encrusted).unwrap();
+
+        assert_eq!(u64::from(SERDE_FORMAT_VERSION), value["version"]);
+        assert_eq!(1, value["algorithm_version"]);
+        assert_eq!(0x2357_bd11, value["key"]);
+
+        let data = value["data"].as_array().unwrap();
+        assert_eq!(8 + TEST_STRING.len(), data.len());
+        assert!(!serde_json::to_string(&value).unwrap().contains("quick"));
+    }
+
+    #[test]
+    fn invalid_input() {
+        let encrusted = Encrusted::new(TEST_STRING.to_string(), 0x2357_bd11);
+        let value = serde_json::to_value(&encrusted).unwrap();
+
+        let mut version = value.clone();
+        version["version"] = 2.into();
+        assert!(serde_json::from_value::<Encrusted<String>>(version).is_err());
+
+        let mut algorithm_version = value.clone();
+        algorithm_version["algorithm_version"] = 0.into();
+        assert!(serde_json::from_value::<Encrusted<String>>(algorithm_version).is_err());
+
+        let mut truncated = value.clone();
+        truncated["data"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<Encrusted<String>>(truncated).is_err());
+
+        // Flipping the highest bit of the first obfuscated character makes it an invalid UTF-8
+        // sequence once deobfuscated.
+        let mut invalid_utf8 = value;
+        let byte = invalid_utf8["data"][8].as_u64().unwrap();
+        invalid_utf8["data"][8] = (byte ^ 0x80).into();
+        assert!(serde_json::from_value::<Encrusted<String>>(invalid_utf8).is_err());
+    }
+
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn chacha20() {
+        use crate::ChaCha20Backend;
+
+        let encrusted =
+            Encrusted::<_, ChaCha20Backend>::with_key(TEST_STRING.to_string(), [0x42; 32]);
+        let json = serde_json::to_string(&encrusted).unwrap();
+
+        let mut deserialized: Encrusted<String, ChaCha20Backend> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(TEST_STRING, deserialized.decrust().as_str());
+    }
+}
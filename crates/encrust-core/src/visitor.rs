@@ -0,0 +1,278 @@
+//! Safe visitor API for implementing [`Encrustable`] without `unsafe` code.
+//!
+//! Types implementing [`VisitEncrust`] describe the parts of themselves that hold data, and the
+//! [`EncrustVisitor`] combines them with the keystream. Only data that is valid for any
+//! combination of bytes can be visited, so obfuscated data can never be observed in an invalid
+//! state, even if a visitor is used on data that is not owned by an [`Encrusted`] object.
+//!
+//! [`Encrusted`]: crate::Encrusted
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::{Bytes, Encrustable, Keystream};
+
+/// Safe alternative to implementing [`Encrustable`] manually. Every type implementing
+/// `VisitEncrust` implements `Encrustable`, obfuscating the data visited by
+/// [`VisitEncrust::visit_encrust`].
+///
+/// Obfuscating and deobfuscating data visits the same parts in the same order, so the data is
+/// always restored as long as the parts that are visited do not depend on data that has already
+/// been visited. Fields that are not valid for every combination of bytes, such as `String`, cannot
+/// be visited, use `Vec<u8>` or [`Bytes`] instead, or derive `Encrustable`.
+///
+/// The heap allocations of fields visited using [`EncrustVisitor::visit_field`] are locked by the
+/// `harden` feature, while slices visited using [`EncrustVisitor::visit_bytes`] are not.
+///
+/// # Example
+/// ```
+/// use encrust_core::{EncrustVisitor, Encrusted, Keystream, VisitEncrust};
+/// use zeroize::Zeroize;
+///
+/// #[derive(Zeroize)]
+/// struct Credentials {
+///     id: u64,
+///     key: [u8; 16],
+///     history: Vec<u32>,
+/// }
+///
+/// impl VisitEncrust for Credentials {
+///     fn visit_encrust(&mut self, visitor: &mut EncrustVisitor<'_, impl Keystream>) {
+///         visitor.visit_field(&mut self.id);
+///         visitor.visit_bytes(&mut self.key);
+///         visitor.visit_field(&mut self.history);
+///     }
+/// }
+///
+/// let credentials = Credentials {
+///     id: 1337,
+///     key: [0x42; 16],
+///     history: vec![1, 2, 3],
+/// };
+/// let mut credentials = Encrusted::new(credentials, 0xc0ffee);
+/// assert_eq!(1337, credentials.decrust().id);
+/// assert_eq!([0x42; 16], credentials.decrust().key);
+/// ```
+pub trait VisitEncrust {
+    /// Visits every part of `self` holding data that should be obfuscated.
+    fn visit_encrust(&mut self, visitor: &mut EncrustVisitor<'_, impl Keystream>);
+}
+
+impl<T> Encrustable for T
+where
+    T: VisitEncrust,
+{
+    unsafe fn toggle_encrust(&mut self, encrust_rng: &mut impl Keystream) {
+        self.visit_encrust(&mut EncrustVisitor {
+            pass: Pass::Toggle(encrust_rng),
+        });
+    }
+
+    fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+        self.visit_encrust(&mut EncrustVisitor::<NoKeystream> {
+            pass: Pass::Allocations(visitor),
+        });
+    }
+}
+
+/// Visitor combining the parts of [`VisitEncrust`] types with the keystream. Visitors are only
+/// created by encrust while obfuscating or deobfuscating data, or while finding the heap
+/// allocations of the data.
+pub struct EncrustVisitor<'a, K>
+where
+    K: Keystream,
+{
+    pass: Pass<'a, K>,
+}
+
+/// What an [`EncrustVisitor`] does with the visited data.
+enum Pass<'a, K> {
+    /// Obfuscates or deobfuscates the data using the keystream.
+    Toggle(&'a mut K),
+    /// Reports the heap allocations of the data, see [`Encrustable::visit_allocations`].
+    Allocations(&'a mut dyn FnMut(*const u8, usize)),
+}
+
+impl<K> EncrustVisitor<'_, K>
+where
+    K: Keystream,
+{
+    /// Obfuscates or deobfuscates `bytes`.
+    ///
+    /// The allocation holding `bytes` is not known, use [`EncrustVisitor::visit_field`] for
+    /// `Vec<u8>` and [`Bytes`] fields so that their allocations are locked by the `harden`
+    /// feature.
+    pub fn visit_bytes(&mut self, bytes: &mut [u8]) {
+        if let Pass::Toggle(keystream) = &mut self.pass {
+            keystream.xor_into(bytes);
+        }
+    }
+
+    /// Obfuscates or deobfuscates `field` the same way as [`Encrustable::toggle_encrust`], see
+    /// [`EncrustField`] for the types that can be visited.
+    pub fn visit_field(&mut self, field: &mut impl EncrustField) {
+        match &mut self.pass {
+            // SAFETY:
+            // Every `EncrustField` is valid for any combination of bytes, so the field is valid
+            // while it is obfuscated.
+            Pass::Toggle(keystream) => unsafe { field.toggle_encrust(*keystream) },
+            Pass::Allocations(visitor) => field.visit_allocations(*visitor),
+        }
+    }
+}
+
+/// Keystream type of visitors reporting allocations, which never use the keystream.
+struct NoKeystream;
+
+impl Keystream for NoKeystream {
+    fn fill(&mut self, _dst: &mut [u8]) {
+        unreachable!("Visitors reporting allocations do not use the keystream");
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Trait implemented by fields that can be visited by [`EncrustVisitor::visit_field`]: integers,
+/// [`Bytes`], arrays and vectors of such fields, and types implementing [`VisitEncrust`]. These
+/// types are valid for any combination of bytes.
+///
+/// This trait is sealed, implement [`VisitEncrust`] to make a type visitable.
+pub trait EncrustField: Encrustable + private::Sealed {}
+
+macro_rules! encrust_field_number {
+    ( $( $t:ty ),* ) => {
+        $(
+            impl private::Sealed for $t {}
+
+            impl EncrustField for $t {}
+        )*
+    };
+}
+
+encrust_field_number!(
+    u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize
+);
+
+impl private::Sealed for Bytes {}
+
+impl EncrustField for Bytes {}
+
+impl<T, const N: usize> private::Sealed for [T; N] where T: EncrustField {}
+
+impl<T, const N: usize> EncrustField for [T; N] where T: EncrustField {}
+
+impl<T> private::Sealed for Vec<T> where T: EncrustField {}
+
+impl<T> EncrustField for Vec<T> where T: EncrustField {}
+
+impl<T> private::Sealed for T where T: VisitEncrust {}
+
+impl<T> EncrustField for T where T: VisitEncrust {}
+
+#[cfg(test)]
+mod tests {
+    use zeroize::Zeroize;
+
+    use super::*;
+    use crate::Encrusted;
+
+    #[derive(Clone, Debug, PartialEq, Zeroize)]
+    struct Inner {
+        key: [u8; 16],
+        bytes: Bytes,
+    }
+
+    impl VisitEncrust for Inner {
+        fn visit_encrust(&mut self, visitor: &mut EncrustVisitor<'_, impl Keystream>) {
+            visitor.visit_bytes(&mut self.key);
+            visitor.visit_field(&mut self.bytes);
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Zeroize)]
+    struct Outer {
+        id: u64,
+        numbers: Vec<[i32; 2]>,
+        inner: Inner,
+    }
+
+    impl VisitEncrust for Outer {
+        fn visit_encrust(&mut self, visitor: &mut EncrustVisitor<'_, impl Keystream>) {
+            visitor.visit_field(&mut self.id);
+            visitor.visit_field(&mut self.numbers);
+            visitor.visit_field(&mut self.inner);
+        }
+    }
+
+    fn outer() -> Outer {
+        Outer {
+            id: 1337,
+            numbers: vec![[1, -1], [i32::MAX, i32::MIN]],
+            inner: Inner {
+                key: [0x42; 16],
+                bytes: Bytes::from(b"The quick brown fox jumps over the lazy dog".as_slice()),
+            },
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut encrusted = Encrusted::new(outer(), 0x2357_bd11);
+        assert_ne!(outer().id, encrusted.data.id);
+        assert_ne!(outer().inner.key, encrusted.data.inner.key);
+        assert_ne!(outer().inner.bytes, encrusted.data.inner.bytes);
+
+        assert_eq!(outer(), *encrusted.decrust());
+
+        encrusted.reseed(0x1317_1d1f);
+        assert_eq!(outer(), *encrusted.decrust());
+    }
+
+    #[test]
+    fn fields_match_encrustable() {
+        struct Numbers(Vec<u32>);
+
+        impl VisitEncrust for Numbers {
+            fn visit_encrust(&mut self, visitor: &mut EncrustVisitor<'_, impl Keystream>) {
+                visitor.visit_field(&mut self.0);
+            }
+        }
+
+        impl Zeroize for Numbers {
+            fn zeroize(&mut self) {
+                self.0.zeroize();
+            }
+        }
+
+        let numbers: Vec<u32> = (0..100).collect();
+        let visited = Encrusted::new(Numbers(numbers.clone()), 0x2357_bd11);
+        let encrusted = Encrusted::new(numbers, 0x2357_bd11);
+
+        assert_eq!(encrusted.data, visited.data.0);
+    }
+
+    #[test]
+    fn allocations() {
+        let mut data = outer();
+        let mut allocations = Vec::new();
+        data.visit_allocations(&mut |ptr, size| allocations.push((ptr, size)));
+
+        assert!(allocations.contains(&(
+            data.numbers.as_ptr().cast(),
+            data.numbers.capacity() * size_of::<[i32; 2]>()
+        )));
+        assert!(allocations.contains(&(data.inner.bytes.as_ptr(), data.inner.bytes.capacity())));
+        assert_eq!(2, allocations.len());
+    }
+
+    #[test]
+    fn nested_in_containers() {
+        let mut encrusted = Encrusted::new(vec![outer(), outer()], 0x2357_bd11);
+        assert_eq!(vec![outer(), outer()], *encrusted.decrust());
+
+        let mut encrusted = Encrusted::new([outer()], 0x2357_bd11);
+        assert_eq!([outer()], *encrusted.decrust());
+    }
+}
@@ -1,7 +1,7 @@
 use proc_macro::TokenStream;
 use quote::{quote, quote_spanned};
 use syn::{
-    Data, DeriveInput, Fields, GenericParam, Generics, Ident, Index, Variant, parse_quote,
+    Data, DeriveInput, Fields, GenericParam, Generics, Ident, Index, Type, Variant, parse_quote,
     spanned::Spanned,
 };
 
@@ -15,13 +15,19 @@ pub fn derive_encrustable(input: DeriveInput) -> TokenStream {
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let encrypatble_impl = gen_encrustable_impl(&input.data);
+    let visit_allocations_impl = gen_visit_allocations_impl(&input.data);
+    let decrust_before_drop = gen_decrust_before_drop(&input.data);
 
     quote! {
         #[doc(hidden)]
         impl #impl_generics ::encrust_core::Encrustable for #name #ty_generics #where_clause  {
-            unsafe fn toggle_encrust(&mut self, encruster: &mut impl ::rand::RngCore) {
+            unsafe fn toggle_encrust(&mut self, encruster: &mut impl ::encrust_core::Keystream) {
                 #encrypatble_impl
             }
+
+            #visit_allocations_impl
+
+            #decrust_before_drop
         }
     }
     .into()
@@ -144,3 +150,340 @@ fn gen_variant_fields_calls(variant: &Variant) -> proc_macro2::TokenStream {
         Fields::Unit => quote! {Self::#variant_name => {}},
     }
 }
+
+fn field_types(data: &Data) -> Vec<&Type> {
+    match data {
+        Data::Struct(struct_data) => struct_data.fields.iter().map(|field| &field.ty).collect(),
+        Data::Enum(enum_data) => enum_data
+            .variants
+            .iter()
+            .flat_map(|variant| variant.fields.iter().map(|field| &field.ty))
+            .collect(),
+        Data::Union(_) => Vec::new(),
+    }
+}
+
+fn gen_decrust_before_drop(data: &Data) -> proc_macro2::TokenStream {
+    let field_types = field_types(data);
+
+    if field_types.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        const DECRUST_BEFORE_DROP: bool =
+            false #(|| <#field_types as ::encrust_core::Encrustable>::DECRUST_BEFORE_DROP)*;
+    }
+}
+
+fn gen_visit_allocations_impl(data: &Data) -> proc_macro2::TokenStream {
+    let has_fields = match data {
+        Data::Struct(struct_data) => !struct_data.fields.is_empty(),
+        Data::Enum(enum_data) => enum_data
+            .variants
+            .iter()
+            .any(|variant| !variant.fields.is_empty()),
+        Data::Union(_) => false,
+    };
+
+    // Use the default implementation if there is nothing to visit, avoiding an unused argument.
+    if !has_fields {
+        return quote! {};
+    }
+
+    let visit_impl = match data {
+        Data::Struct(struct_data) => {
+            let field_calls = struct_data.fields.iter().enumerate().map(|(index, field)| {
+                let member = field.ident.as_ref().map_or_else(
+                    || {
+                        let index = Index::from(index);
+                        quote! {#index}
+                    },
+                    |name| quote! {#name},
+                );
+
+                quote_spanned! {field.span()=>
+                    ::encrust_core::Encrustable::visit_allocations(&mut self.#member, visitor);
+                }
+            });
+
+            quote! {#(#field_calls) *}
+        }
+        Data::Enum(enum_data) => {
+            let variants = enum_data.variants.iter().map(|variant| {
+                let (pattern, names) = gen_variant_pattern(variant);
+
+                quote! {#pattern => {
+                    #(::encrust_core::Encrustable::visit_allocations(#names, visitor);)*
+                }}
+            });
+
+            quote! {match self {
+                #(#variants )*
+            }}
+        }
+        Data::Union(_) => quote! {},
+    };
+
+    quote! {
+        fn visit_allocations(&mut self, visitor: &mut dyn FnMut(*const u8, usize)) {
+            #visit_impl
+        }
+    }
+}
+
+#[cfg(feature = "integrity")]
+pub fn derive_authenticate(input: DeriveInput) -> TokenStream {
+    let name = input.ident;
+    let generics = add_authenticate_bounds(input.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let authenticate_impl = gen_authenticate_impl(&input.data);
+
+    quote! {
+        #[doc(hidden)]
+        impl #impl_generics ::encrust_core::Authenticate for #name #ty_generics #where_clause  {
+            fn authenticate(&self, mac: &mut ::encrust_core::IntegrityMac) {
+                #authenticate_impl
+            }
+        }
+    }
+    .into()
+}
+
+#[cfg(feature = "integrity")]
+fn add_authenticate_bounds(mut generics: Generics) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(ref mut type_param) = *param {
+            type_param
+                .bounds
+                .push(parse_quote!(::encrust_core::Authenticate));
+        }
+    }
+    generics
+}
+
+#[cfg(feature = "integrity")]
+fn gen_authenticate_impl(data: &Data) -> proc_macro2::TokenStream {
+    match data {
+        Data::Struct(struct_data) => {
+            let field_calls = struct_data.fields.iter().enumerate().map(|(index, field)| {
+                let member = field.ident.as_ref().map_or_else(
+                    || {
+                        let index = Index::from(index);
+                        quote! {#index}
+                    },
+                    |name| quote! {#name},
+                );
+
+                quote_spanned! {field.span()=>
+                    ::encrust_core::Authenticate::authenticate(&self.#member, mac);
+                }
+            });
+
+            quote! {#(#field_calls) *}
+        }
+        Data::Enum(enum_data) => {
+            // The variant index is included in the MAC as the discriminant is not obfuscated and
+            // could be changed without modifying any of the fields.
+            let variants = enum_data
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(variant_index, variant)| {
+                    let variant_index = u32::try_from(variant_index)
+                        .expect("Enums with more than u32::MAX variants are not supported");
+                    let (pattern, names) = gen_variant_pattern(variant);
+
+                    quote! {#pattern => {
+                        mac.update(&#variant_index.to_le_bytes());
+                        #(::encrust_core::Authenticate::authenticate(#names, mac);)*
+                    }}
+                });
+
+            quote! {match self {
+                #(#variants )*
+            }}
+        }
+
+        Data::Union(_) => quote! { compile_error!("`Authenticate` does not support unions.");},
+    }
+}
+
+pub fn derive_encode_obfuscated(input: DeriveInput) -> TokenStream {
+    let name = input.ident;
+    let generics = add_encode_obfuscated_bounds(input.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let (encode_impl, decode_impl, check_decrusted_impl) = gen_encode_obfuscated_impl(&input.data);
+    let field_types = field_types(&input.data);
+
+    #[cfg(feature = "std")]
+    let vec = quote! {::std::vec::Vec};
+    #[cfg(not(feature = "std"))]
+    let vec = quote! {::alloc::vec::Vec};
+
+    quote! {
+        #[doc(hidden)]
+        impl #impl_generics ::encrust_core::EncodeObfuscated for #name #ty_generics #where_clause  {
+            fn encode(&self, out: &mut #vec<u8>) {
+                #encode_impl
+            }
+
+            fn decode(input: &mut &[u8]) -> ::core::option::Option<Self> {
+                #decode_impl
+            }
+
+            const CHECK_DECRUSTED: bool =
+                false #(|| <#field_types as ::encrust_core::EncodeObfuscated>::CHECK_DECRUSTED)*;
+
+            fn check_decrusted(&self) -> bool {
+                #check_decrusted_impl
+            }
+        }
+    }
+    .into()
+}
+
+fn add_encode_obfuscated_bounds(mut generics: Generics) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(ref mut type_param) = *param {
+            type_param
+                .bounds
+                .push(parse_quote!(::encrust_core::EncodeObfuscated));
+        }
+    }
+    generics
+}
+
+/// Returns the constructor of `fields`, decoding each field in order.
+fn gen_decode_constructor(
+    path: &proc_macro2::TokenStream,
+    fields: &Fields,
+) -> proc_macro2::TokenStream {
+    let decode = quote! {::encrust_core::EncodeObfuscated::decode(input)?};
+
+    match fields {
+        Fields::Named(named_fields) => {
+            let names = named_fields.named.iter().map(|field| &field.ident);
+
+            quote! {#path { #(#names: #decode),* }}
+        }
+
+        Fields::Unnamed(numbered_fields) => {
+            let decodes = numbered_fields.unnamed.iter().map(|_| &decode);
+
+            quote! {#path ( #(#decodes),* )}
+        }
+
+        Fields::Unit => quote! {#path},
+    }
+}
+
+/// Returns the bodies of `encode`, `decode` and `check_decrusted`.
+fn gen_encode_obfuscated_impl(
+    data: &Data,
+) -> (
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+    proc_macro2::TokenStream,
+) {
+    match data {
+        Data::Struct(struct_data) => {
+            let members = struct_data
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    field.ident.as_ref().map_or_else(
+                        || {
+                            let index = Index::from(index);
+                            quote! {#index}
+                        },
+                        |name| quote! {#name},
+                    )
+                })
+                .collect::<Vec<_>>();
+            let constructor = gen_decode_constructor(&quote! {Self}, &struct_data.fields);
+
+            (
+                quote! {#(::encrust_core::EncodeObfuscated::encode(&self.#members, out);)*},
+                quote! {::core::option::Option::Some(#constructor)},
+                quote! {true #(&& ::encrust_core::EncodeObfuscated::check_decrusted(&self.#members))*},
+            )
+        }
+        Data::Enum(enum_data) => {
+            // The variant index is encoded before the fields, as it is needed to decode them.
+            let mut encode_variants = Vec::new();
+            let mut decode_variants = Vec::new();
+            let mut check_variants = Vec::new();
+
+            for (variant_index, variant) in enum_data.variants.iter().enumerate() {
+                let variant_index = u32::try_from(variant_index)
+                    .expect("Enums with more than u32::MAX variants are not supported");
+                let variant_name = &variant.ident;
+                let (pattern, names) = gen_variant_pattern(variant);
+                let constructor =
+                    gen_decode_constructor(&quote! {Self::#variant_name}, &variant.fields);
+
+                encode_variants.push(quote! {#pattern => {
+                    ::encrust_core::EncodeObfuscated::encode(&#variant_index, out);
+                    #(::encrust_core::EncodeObfuscated::encode(#names, out);)*
+                }});
+                decode_variants.push(quote! {
+                    #variant_index => ::core::option::Option::Some(#constructor),
+                });
+                check_variants.push(quote! {#pattern => {
+                    true #(&& ::encrust_core::EncodeObfuscated::check_decrusted(#names))*
+                }});
+            }
+
+            (
+                quote! {match self {
+                    #(#encode_variants )*
+                }},
+                quote! {match <u32 as ::encrust_core::EncodeObfuscated>::decode(input)? {
+                    #(#decode_variants )*
+                    _ => ::core::option::Option::None,
+                }},
+                quote! {match self {
+                    #(#check_variants )*
+                }},
+            )
+        }
+
+        Data::Union(_) => {
+            let error = quote! { compile_error!("`EncodeObfuscated` does not support unions.");};
+
+            (error, quote! {}, quote! {})
+        }
+    }
+}
+
+fn gen_variant_pattern(variant: &Variant) -> (proc_macro2::TokenStream, Vec<Ident>) {
+    let variant_name = &variant.ident;
+    match &variant.fields {
+        Fields::Named(named_fields) => {
+            let names = named_fields
+                .named
+                .iter()
+                .filter_map(|field| field.ident.clone())
+                .collect::<Vec<_>>();
+
+            (quote! {Self::#variant_name { #(#names),* }}, names)
+        }
+
+        Fields::Unnamed(numbered_fields) => {
+            let names = numbered_fields
+                .unnamed
+                .iter()
+                .enumerate()
+                .map(|(index, field)| Ident::new(&format!("field_{index}"), field.span()))
+                .collect::<Vec<_>>();
+
+            (quote! {Self::#variant_name ( #(#names),* )}, names)
+        }
+
+        Fields::Unit => (quote! {Self::#variant_name}, Vec::new()),
+    }
+}
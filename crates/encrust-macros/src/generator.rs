@@ -1,9 +1,19 @@
-use encrust_core::{Encrustable, Hashbytes, Hashstring, Sensitivity};
+#[cfg(feature = "aes")]
+use encrust_core::AesCtrBackend;
+#[cfg(feature = "chacha20")]
+use encrust_core::ChaCha20Backend;
+#[cfg(feature = "integrity")]
+use encrust_core::IntegrityMac;
+use encrust_core::{
+    Backend, Bytes, Encrustable, Hashbytes, Hashstring, Keystream, Sensitivity, SmallRngBackend,
+};
 use proc_macro2::Span;
 use quote::{quote, quote_spanned};
-use rand::{RngCore, SeedableRng, rngs::SmallRng};
+use rand::RngCore;
 
-use crate::parser::{FilePath, Literal, LiteralVec, ToHashBytes, ToHashString};
+use crate::parser::{
+    BackendChoice, FilePath, Literal, LiteralVec, Options, ToHashBytes, ToHashString,
+};
 
 #[derive(Debug)]
 pub struct TokenStreamError {
@@ -19,25 +29,119 @@ impl std::fmt::Display for TokenStreamError {
     }
 }
 
+/// Calculates the tags of obfuscated data for authenticated output.
+///
+/// `usize` and `isize` are obfuscated and authenticated as 64-bit numbers, but 32-bit targets only
+/// keep the lower half of the obfuscated numbers, so a separate tag is calculated for them.
+#[derive(Default)]
+pub struct Authenticator {
+    #[cfg(feature = "integrity")]
+    macs: Option<(IntegrityMac, IntegrityMac)>,
+}
+
+impl Authenticator {
+    /// Creates an authenticator calculating the tags of data obfuscated using `key`.
+    #[cfg(feature = "integrity")]
+    fn new<B: Backend>(key: &B::Key) -> Self {
+        Self {
+            macs: Some((IntegrityMac::new::<B>(key), IntegrityMac::new::<B>(key))),
+        }
+    }
+
+    /// Adds `bytes` to the tags of all targets.
+    fn update(&mut self, bytes: &[u8]) {
+        self.update_sized(bytes, bytes);
+    }
+
+    /// Adds `wide` to the tag of 64-bit targets and `narrow` to the tag of 32-bit targets.
+    #[cfg_attr(
+        not(feature = "integrity"),
+        expect(
+            clippy::unused_self,
+            reason = "Tags are only calculated with `integrity`"
+        )
+    )]
+    fn update_sized(&mut self, wide: &[u8], narrow: &[u8]) {
+        #[cfg(feature = "integrity")]
+        if let Some((wide_mac, narrow_mac)) = &mut self.macs {
+            wide_mac.update(wide);
+            narrow_mac.update(narrow);
+        }
+        #[cfg(not(feature = "integrity"))]
+        let _ = (wide, narrow);
+    }
+
+    /// Returns the tokens selecting the tag matching the pointer width of the target.
+    #[cfg(feature = "integrity")]
+    fn tag_tokens(self) -> proc_macro2::TokenStream {
+        let Some((wide_mac, narrow_mac)) = self.macs else {
+            unreachable!("Tags are only requested from authenticators created using `new`");
+        };
+        let wide_tag = wide_mac.finalize();
+        let narrow_tag = narrow_mac.finalize();
+
+        if wide_tag == narrow_tag {
+            quote! {[#(#wide_tag),*]}
+        } else {
+            quote! {
+                if cfg!(target_pointer_width = "32") {
+                    [#(#narrow_tag),*]
+                } else {
+                    [#(#wide_tag),*]
+                }
+            }
+        }
+    }
+}
+
 pub trait ToEncrustedTokenStream {
+    /// Returns the tokens for the encrusted data. The bytes fed to `authenticator` must match the
+    /// bytes fed to the MAC by `encrust_core::Authenticate` for the resulting data type.
     fn to_token_stream(
         &self,
-        encruster: &mut impl RngCore,
+        encruster: &mut impl Keystream,
+        authenticator: &mut Authenticator,
     ) -> Result<proc_macro2::TokenStream, TokenStreamError>;
 
-    fn generate_output_tokens(&self) -> proc_macro::TokenStream {
-        let seed = rand::rng().next_u64();
-        let mut encruster = SmallRng::seed_from_u64(seed);
+    fn generate_output_tokens(&self, options: &Options) -> proc_macro::TokenStream {
+        let mut rng = rand::rng();
 
-        match self.to_token_stream(&mut encruster) {
-            Ok(token_stream) => quote! {
-                unsafe {
-                    ::encrust::Encrusted::from_encrusted_data(
-                        #token_stream,
-                        #seed
-                    )
-                }
-            },
+        let output = match options.backend {
+            BackendChoice::SmallRng => {
+                let seed = rng.next_u64();
+                self.encrusted_with_backend::<SmallRngBackend>(
+                    options,
+                    &seed,
+                    &quote! {#seed},
+                    &quote! {SmallRngBackend},
+                )
+            }
+            #[cfg(feature = "chacha20")]
+            BackendChoice::ChaCha20 => {
+                let mut key = [0u8; 32];
+                rng.fill_bytes(&mut key);
+                self.encrusted_with_backend::<ChaCha20Backend>(
+                    options,
+                    &key,
+                    &quote! {[#(#key),*]},
+                    &quote! {ChaCha20Backend},
+                )
+            }
+            #[cfg(feature = "aes")]
+            BackendChoice::AesCtr => {
+                let mut key = [0u8; 32];
+                rng.fill_bytes(&mut key);
+                self.encrusted_with_backend::<AesCtrBackend>(
+                    options,
+                    &key,
+                    &quote! {[#(#key),*]},
+                    &quote! {AesCtrBackend},
+                )
+            }
+        };
+
+        match output {
+            Ok(token_stream) => token_stream,
             Err(error) => {
                 let error_message = format!("{error}");
                 quote_spanned! {error.span=>
@@ -47,38 +151,223 @@ pub trait ToEncrustedTokenStream {
         }
         .into()
     }
+
+    fn encrusted_with_backend<B: Backend>(
+        &self,
+        options: &Options,
+        key: &B::Key,
+        key_tokens: &proc_macro2::TokenStream,
+        backend: &proc_macro2::TokenStream,
+    ) -> Result<proc_macro2::TokenStream, TokenStreamError> {
+        let mut encruster = B::keystream(key);
+        // The version is included so that data embedded by this version of the macros can still
+        // be deobfuscated if the algorithm of the backend changes.
+        let version = B::VERSION;
+        // Fails to compile if encrust-core is older than the macros, instead of panicking at
+        // runtime because the version is unsupported.
+        let version_check = quote! {
+            const {
+                assert!(
+                    <::encrust::#backend as ::encrust::Backend>::VERSION >= #version,
+                    "encrust-macros uses a newer algorithm version than encrust-core supports"
+                );
+            }
+        };
+
+        #[cfg(feature = "integrity")]
+        if options.authenticated {
+            let mut authenticator = Authenticator::new::<B>(key);
+            let token_stream = self.to_token_stream(&mut encruster, &mut authenticator)?;
+            let tag = authenticator.tag_tokens();
+
+            return Ok(quote! {
+                {
+                    #version_check
+                    unsafe {
+                        ::encrust::Encrusted::<_, ::encrust::#backend>::from_authenticated_encrusted_data(
+                            #token_stream,
+                            #key_tokens,
+                            #tag,
+                            #version
+                        )
+                    }
+                }
+            });
+        }
+        #[cfg(not(feature = "integrity"))]
+        let _ = options;
+
+        let token_stream = self.to_token_stream(&mut encruster, &mut Authenticator::default())?;
+
+        Ok(quote! {
+            {
+                #version_check
+                unsafe {
+                    ::encrust::Encrusted::<_, ::encrust::#backend>::from_encrusted_data(
+                        #token_stream,
+                        #key_tokens,
+                        #version
+                    )
+                }
+            }
+        })
+    }
+}
+
+/// Returns the lower half of an obfuscated `usize`, which is all that is kept on 32-bit targets,
+/// zero-extended like `encrust_core::Authenticate` does on those targets.
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "32-bit targets only keep the lower half"
+)]
+fn narrow_usize(n: u64) -> u64 {
+    u64::from(n as u32)
+}
+
+/// Returns the lower half of an obfuscated `isize`, which is all that is kept on 32-bit targets,
+/// sign-extended like `encrust_core::Authenticate` does on those targets.
+#[expect(
+    clippy::cast_possible_truncation,
+    reason = "32-bit targets only keep the lower half"
+)]
+fn narrow_isize(n: i64) -> i64 {
+    i64::from(n as i32)
+}
+
+/// Returns the tokens for an obfuscated number, cast to `$type` if given.
+macro_rules! number_tokens {
+    ($n:ident) => {
+        quote! {#$n}
+    };
+    ($n:ident as $type:ident) => {
+        quote! {(#$n as $type)}
+    };
 }
 
 macro_rules! number_to_token_stream {
-    ($num:ident, $encruster:ident) => {{
+    ($num:ident, $encruster:ident, $authenticator:ident) => {{
         let mut n = *$num;
         // Safety: The underlying data must be encrusted to be used with `from_encrusted_data`.
         // It should not be exposed without calling `toggle_encrust` again.
         unsafe {
             n.toggle_encrust($encruster);
         }
+        $authenticator.update(&n.to_le_bytes());
         quote! {#n}
     }};
+    // `usize` and `isize` are obfuscated and emitted as 64-bit numbers regardless of platform.
+    // The emitted numbers are cast to the target's pointer width, keeping the bits matching the
+    // keystream used on 32-bit targets.
+    (
+        $num:ident,
+        $encruster:ident,
+        $authenticator:ident as $wide_type:ident => $type:ident, $narrow:ident
+    ) => {{
+        let mut n = *$num;
+        // Safety: The underlying data must be encrusted to be used with `from_encrusted_data`.
+        // It should not be exposed without calling `toggle_encrust` again.
+        unsafe {
+            n.toggle_encrust($encruster);
+        }
+        let wide = n as $wide_type;
+        let narrow = $narrow(wide);
+        $authenticator.update_sized(&wide.to_le_bytes(), &narrow.to_le_bytes());
+        number_tokens!(wide as $type)
+    }};
+}
+
+macro_rules! numbers_to_token_streams {
+    (
+        $items:ident,
+        $encruster:ident,
+        $authenticator:ident,
+        $( $variant:ident $( as $wide_type:ident => $type:ident, $narrow:ident )? ),*
+    ) => {
+        match $items.first() {
+            $(
+                Some(Literal::$variant(_)) => $items
+                    .iter()
+                    .map(|el| match el {
+                        Literal::$variant(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(|mut numbers| {
+                        // Safety: The underlying data must be encrusted to be used with
+                        // `from_encrusted_data`. It should not be exposed without calling
+                        // `toggle_encrust` again.
+                        unsafe {
+                            numbers.toggle_encrust($encruster);
+                        }
+
+                        numbers
+                            .iter()
+                            .map(|n| {
+                                let wide = *n $( as $wide_type )?;
+                                let narrow = $( $narrow )?(wide);
+                                $authenticator.update_sized(
+                                    &wide.to_le_bytes(),
+                                    &narrow.to_le_bytes(),
+                                );
+                                number_tokens!(wide $( as $type )?)
+                            })
+                            .collect()
+                    }),
+            )*
+            _ => None,
+        }
+    };
+}
+
+/// Returns the tokens for each number if all `items` are numbers of the same type. The numbers are
+/// encrusted together, matching how `encrust_core` encrusts arrays and vecs of numbers.
+fn numbers_to_token_streams(
+    items: &[Literal],
+    encruster: &mut impl Keystream,
+    authenticator: &mut Authenticator,
+) -> Option<Vec<proc_macro2::TokenStream>> {
+    numbers_to_token_streams!(
+        items,
+        encruster,
+        authenticator,
+        U8,
+        U16,
+        U32,
+        U64,
+        U128,
+        Usize as u64 => usize, narrow_usize,
+        I8,
+        I16,
+        I32,
+        I64,
+        I128,
+        Isize as i64 => isize, narrow_isize
+    )
 }
 
 impl ToEncrustedTokenStream for Literal {
     fn to_token_stream(
         &self,
-        encruster: &mut impl RngCore,
+        encruster: &mut impl Keystream,
+        authenticator: &mut Authenticator,
     ) -> Result<proc_macro2::TokenStream, TokenStreamError> {
         Ok(match self {
-            Self::U8(n) => number_to_token_stream!(n, encruster),
-            Self::U16(n) => number_to_token_stream!(n, encruster),
-            Self::U32(n) => number_to_token_stream!(n, encruster),
-            Self::U64(n) => number_to_token_stream!(n, encruster),
-            Self::U128(n) => number_to_token_stream!(n, encruster),
-            Self::Usize(n) => number_to_token_stream!(n, encruster),
-            Self::I8(n) => number_to_token_stream!(n, encruster),
-            Self::I16(n) => number_to_token_stream!(n, encruster),
-            Self::I32(n) => number_to_token_stream!(n, encruster),
-            Self::I64(n) => number_to_token_stream!(n, encruster),
-            Self::I128(n) => number_to_token_stream!(n, encruster),
-            Self::Isize(n) => number_to_token_stream!(n, encruster),
+            Self::U8(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::U16(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::U32(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::U64(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::U128(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::Usize(n) => {
+                number_to_token_stream!(n, encruster, authenticator as u64 => usize, narrow_usize)
+            }
+            Self::I8(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::I16(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::I32(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::I64(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::I128(n) => number_to_token_stream!(n, encruster, authenticator),
+            Self::Isize(n) => {
+                number_to_token_stream!(n, encruster, authenticator as i64 => isize, narrow_isize)
+            }
             Self::String(s) => {
                 let mut string = s.clone();
 
@@ -90,6 +379,8 @@ impl ToEncrustedTokenStream for Literal {
                 }
 
                 let bytes = Vec::from(string.as_bytes());
+                authenticator.update(&(bytes.len() as u64).to_le_bytes());
+                authenticator.update(&bytes);
 
                 #[cfg(feature = "std")]
                 quote! {unsafe { ::std::string::String::from_utf8_unchecked([#(#bytes),*].to_vec()) }}
@@ -97,9 +388,15 @@ impl ToEncrustedTokenStream for Literal {
                 quote! {unsafe { ::alloc::string::String::from_utf8_unchecked([#(#bytes),*].to_vec()) }}
             }
             Self::Array(arr) => {
+                if let Some(encrusted_items) =
+                    numbers_to_token_streams(arr, encruster, authenticator)
+                {
+                    return Ok(quote! {[#(#encrusted_items),*]});
+                }
+
                 let encrusted_items = arr
                     .iter()
-                    .map(|el| el.to_token_stream(encruster))
+                    .map(|el| el.to_token_stream(encruster, authenticator))
                     .collect::<Result<Vec<proc_macro2::TokenStream>, TokenStreamError>>()?;
                 quote! {[#(#encrusted_items),*]}
             }
@@ -107,15 +404,59 @@ impl ToEncrustedTokenStream for Literal {
     }
 }
 
+/// Returns the tokens for `encrust_core::Bytes` containing `bytes`.
+fn bytes_to_token_stream(
+    bytes: Vec<u8>,
+    encruster: &mut impl Keystream,
+    authenticator: &mut Authenticator,
+) -> proc_macro2::TokenStream {
+    let mut bytes = Bytes::from(bytes);
+
+    // Safety: The underlying data must be encrusted to be used with `from_encrusted_data`. It
+    // should not be exposed without calling `toggle_encrust` again.
+    unsafe {
+        bytes.toggle_encrust(encruster);
+    }
+
+    authenticator.update(&(bytes.len() as u64).to_le_bytes());
+    authenticator.update(&bytes);
+
+    let byte_string = proc_macro2::Literal::byte_string(&bytes);
+    quote! {::encrust::Bytes::from(#byte_string.to_vec())}
+}
+
 impl ToEncrustedTokenStream for LiteralVec {
     fn to_token_stream(
         &self,
-        encruster: &mut impl RngCore,
+        encruster: &mut impl Keystream,
+        authenticator: &mut Authenticator,
     ) -> Result<proc_macro2::TokenStream, TokenStreamError> {
+        // Vecs of bytes are turned into `Bytes`, which is more efficient to encrust.
+        if !self.0.is_empty() {
+            let bytes = self
+                .0
+                .iter()
+                .map(|el| match el {
+                    Literal::U8(byte) => Some(*byte),
+                    _ => None,
+                })
+                .collect::<Option<Vec<u8>>>();
+
+            if let Some(bytes) = bytes {
+                return Ok(bytes_to_token_stream(bytes, encruster, authenticator));
+            }
+        }
+
+        authenticator.update(&(self.0.len() as u64).to_le_bytes());
+
+        if let Some(encrusted_items) = numbers_to_token_streams(&self.0, encruster, authenticator) {
+            return Ok(quote! {[#(#encrusted_items),*].to_vec()});
+        }
+
         let encrusted_items = self
             .0
             .iter()
-            .map(|el| el.to_token_stream(encruster))
+            .map(|el| el.to_token_stream(encruster, authenticator))
             .collect::<Result<Vec<proc_macro2::TokenStream>, TokenStreamError>>()?;
         Ok(quote! {[#(#encrusted_items),*].to_vec()})
     }
@@ -132,10 +473,11 @@ impl From<FilePath> for StringFileReader {
 impl ToEncrustedTokenStream for StringFileReader {
     fn to_token_stream(
         &self,
-        encruster: &mut impl RngCore,
+        encruster: &mut impl Keystream,
+        authenticator: &mut Authenticator,
     ) -> Result<proc_macro2::TokenStream, TokenStreamError> {
         match std::fs::read_to_string(&self.0.path) {
-            Ok(s) => Literal::String(s).to_token_stream(encruster),
+            Ok(s) => Literal::String(s).to_token_stream(encruster, authenticator),
             Err(error) => Err(TokenStreamError {
                 msg: format!(
                     "Error when attempting to read `{}` to a String: {}",
@@ -159,11 +501,11 @@ impl From<FilePath> for BytesFileReader {
 impl ToEncrustedTokenStream for BytesFileReader {
     fn to_token_stream(
         &self,
-        encruster: &mut impl RngCore,
+        encruster: &mut impl Keystream,
+        authenticator: &mut Authenticator,
     ) -> Result<proc_macro2::TokenStream, TokenStreamError> {
         match std::fs::read(&self.0.path) {
-            Ok(bytes) => Literal::Array(bytes.into_iter().map(Literal::U8).collect())
-                .to_token_stream(encruster),
+            Ok(bytes) => Ok(bytes_to_token_stream(bytes, encruster, authenticator)),
             Err(error) => Err(TokenStreamError {
                 msg: format!(
                     "Error when attempting to read `{}` to a byte array: {}",
@@ -11,7 +11,7 @@ use syn::parse_macro_input;
 
 use crate::{
     generator::{BytesFileReader, StringFileReader, ToEncrustedTokenStream},
-    parser::{FilePath, Literal, LiteralVec, ToHashBytes, ToHashString},
+    parser::{FilePath, Literal, LiteralVec, ToHashBytes, ToHashString, WithOptions},
 };
 
 /// Encrust a literal value so the actual data is obfuscated before being included in the binary.
@@ -19,6 +19,11 @@ use crate::{
 ///
 /// Integers require their data type suffixed (`-1i8`, `127u16` etc).
 ///
+/// The backend used to obfuscate the data can be selected by prefixing the input with
+/// `backend = <Backend>;`, where `<Backend>` is one of `SmallRngBackend` (the default),
+/// `ChaCha20Backend` (requires the `chacha20` feature) or `AesCtrBackend` (requires the `aes`
+/// feature). This option is supported by all the `encrust` macros.
+///
 /// # Examples
 /// ```
 /// # extern crate encrust_core as encrust;
@@ -29,14 +34,18 @@ use crate::{
 /// assert_eq!("This is a string", string.decrust().as_str());
 /// let mut array = encrust!([1i32, 2i32, 3i32]);
 /// assert_eq!(&[1i32, 2i32, 3i32], array.decrust().as_slice());
+/// let mut with_backend = encrust!(backend = SmallRngBackend; "Another string");
+/// assert_eq!("Another string", with_backend.decrust().as_str());
 /// ```
 #[proc_macro]
 pub fn encrust(input: TokenStream) -> TokenStream {
-    parse_macro_input!(input as Literal).generate_output_tokens()
+    let WithOptions { options, input } = parse_macro_input!(input as WithOptions<Literal>);
+    input.generate_output_tokens(&options)
 }
 
 /// Encrust a vec of literals. This works similarly to [`encrust!`] and supports the same data
-/// types, but puts the data in a `vec`.
+/// types, but puts the data in a `vec`. A vec of `u8` literals is put in `encrust::Bytes` instead,
+/// which is more efficient to obfuscate.
 ///
 /// # Example
 /// ```
@@ -50,7 +59,8 @@ pub fn encrust(input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn encrust_vec(input: TokenStream) -> TokenStream {
-    parse_macro_input!(input as LiteralVec).generate_output_tokens()
+    let WithOptions { options, input } = parse_macro_input!(input as WithOptions<LiteralVec>);
+    input.generate_output_tokens(&options)
 }
 
 /// Read the contents of a file into a string and encrust it so the actual file contents is
@@ -69,10 +79,11 @@ pub fn encrust_vec(input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn encrust_file_string(input: TokenStream) -> TokenStream {
-    StringFileReader::from(parse_macro_input!(input as FilePath)).generate_output_tokens()
+    let WithOptions { options, input } = parse_macro_input!(input as WithOptions<FilePath>);
+    StringFileReader::from(input).generate_output_tokens(&options)
 }
 
-/// Read the contents of a file into a `u8` array and encrust it so the actual file contents is
+/// Read the contents of a file into `encrust::Bytes` and encrust it so the actual file contents is
 /// obfuscated before being included in the binary.
 ///
 /// Unless an absolute path is given, the file is read relative to the `CARGO_MANIFEST_DIR`
@@ -88,7 +99,8 @@ pub fn encrust_file_string(input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn encrust_file_bytes(input: TokenStream) -> TokenStream {
-    BytesFileReader::from(parse_macro_input!(input as FilePath)).generate_output_tokens()
+    let WithOptions { options, input } = parse_macro_input!(input as WithOptions<FilePath>);
+    BytesFileReader::from(input).generate_output_tokens(&options)
 }
 
 /// Hash a string so that it can be searched for in the resulting executable without including the
@@ -147,3 +159,23 @@ pub fn hashbytes(input: TokenStream) -> TokenStream {
 pub fn derive_encrustable_macro(input: TokenStream) -> TokenStream {
     derive::derive_encrustable(parse_macro_input!(input as syn::DeriveInput))
 }
+
+/// Derive macro to allow custom `struct`s and `enum`s to be used with integrity protection.
+///
+/// This requires that all fields implement `Authenticate`, and is typically used together with the
+/// `Encrustable` derive macro.
+#[proc_macro_derive(Authenticate)]
+#[cfg(feature = "integrity")]
+pub fn derive_authenticate_macro(input: TokenStream) -> TokenStream {
+    derive::derive_authenticate(parse_macro_input!(input as syn::DeriveInput))
+}
+
+/// Derive macro to allow `Encrusted` objects holding custom `struct`s and `enum`s to be serialized
+/// and sealed.
+///
+/// This requires that all fields implement `EncodeObfuscated`, and is typically used together with
+/// the `Encrustable` derive macro.
+#[proc_macro_derive(EncodeObfuscated)]
+pub fn derive_encode_obfuscated_macro(input: TokenStream) -> TokenStream {
+    derive::derive_encode_obfuscated(parse_macro_input!(input as syn::DeriveInput))
+}
@@ -1,7 +1,123 @@
 use std::path::{Path, PathBuf};
 
 use proc_macro2::Span;
-use syn::{LitInt, LitStr, Token, bracketed, parse::Parse};
+use syn::{Ident, LitInt, LitStr, Token, bracketed, parse::Parse};
+
+/// Backends that can be selected using the `backend = ...;` option.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum BackendChoice {
+    SmallRng,
+    #[cfg(feature = "chacha20")]
+    ChaCha20,
+    #[cfg(feature = "aes")]
+    AesCtr,
+}
+
+impl Parse for BackendChoice {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let backend: Ident = input.parse()?;
+
+        match backend.to_string().as_str() {
+            "SmallRngBackend" => Ok(Self::SmallRng),
+            #[cfg(feature = "chacha20")]
+            "ChaCha20Backend" => Ok(Self::ChaCha20),
+            #[cfg(not(feature = "chacha20"))]
+            "ChaCha20Backend" => Err(syn::Error::new(
+                backend.span(),
+                "`ChaCha20Backend` requires the `chacha20` feature to be enabled.",
+            )),
+            #[cfg(feature = "aes")]
+            "AesCtrBackend" => Ok(Self::AesCtr),
+            #[cfg(not(feature = "aes"))]
+            "AesCtrBackend" => Err(syn::Error::new(
+                backend.span(),
+                "`AesCtrBackend` requires the `aes` feature to be enabled.",
+            )),
+            _ => Err(syn::Error::new(
+                backend.span(),
+                format!("Unknown backend `{backend}`."),
+            )),
+        }
+    }
+}
+
+/// Options that can be given to the encrust macros before the actual input, separated from the
+/// input by a `;`, for example `encrust!(backend = ChaCha20Backend; "A string")`.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Options {
+    pub backend: BackendChoice,
+    #[cfg(feature = "integrity")]
+    pub authenticated: bool,
+}
+
+impl Parse for Options {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut options = Self {
+            backend: BackendChoice::SmallRng,
+            #[cfg(feature = "integrity")]
+            authenticated: false,
+        };
+
+        // Literals never start with an identifier, so options are only parsed if the input starts
+        // with one.
+        if !input.peek(Ident) {
+            return Ok(options);
+        }
+
+        loop {
+            let option: Ident = input.parse()?;
+
+            match option.to_string().as_str() {
+                "backend" => {
+                    input.parse::<Token![=]>()?;
+                    options.backend = input.parse()?;
+                }
+                #[cfg(feature = "integrity")]
+                "authenticated" => options.authenticated = true,
+                #[cfg(not(feature = "integrity"))]
+                "authenticated" => {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`authenticated` requires the `integrity` feature to be enabled.",
+                    ));
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        format!("Unknown option `{option}`."),
+                    ));
+                }
+            }
+
+            if input.peek(Token![;]) {
+                input.parse::<Token![;]>()?;
+                break;
+            }
+
+            input.parse::<Token![,]>()?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Macro input optionally prefixed by [`Options`].
+pub struct WithOptions<T> {
+    pub options: Options,
+    pub input: T,
+}
+
+impl<T> Parse for WithOptions<T>
+where
+    T: Parse,
+{
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            options: input.parse()?,
+            input: input.parse()?,
+        })
+    }
+}
 
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum Literal {
@@ -243,6 +359,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_options() {
+        let with_options = syn::parse_str::<WithOptions<Literal>>("1u8")
+            .expect("Unable to parse input without options");
+        assert_eq!(BackendChoice::SmallRng, with_options.options.backend);
+        assert_eq!(Literal::U8(1u8), with_options.input);
+
+        let with_options =
+            syn::parse_str::<WithOptions<LiteralVec>>("backend = SmallRngBackend; 1u8")
+                .expect("Unable to parse input with options");
+        assert_eq!(BackendChoice::SmallRng, with_options.options.backend);
+        assert_eq!(LiteralVec(vec![Literal::U8(1u8)]), with_options.input);
+
+        #[cfg(feature = "integrity")]
+        {
+            let with_options = syn::parse_str::<WithOptions<Literal>>(
+                "authenticated, backend = SmallRngBackend; 1u8",
+            )
+            .expect("Unable to parse input with multiple options");
+            assert!(with_options.options.authenticated);
+        }
+    }
+
+    #[test]
+    fn parse_options_fail_on_unknown() {
+        let unknown_option = syn::parse_str::<WithOptions<Literal>>("unknown; 1u8");
+        assert!(unknown_option.is_err());
+
+        let unknown_backend = syn::parse_str::<WithOptions<Literal>>("backend = Unknown; 1u8");
+        assert!(unknown_backend.is_err());
+
+        let missing_semicolon =
+            syn::parse_str::<WithOptions<Literal>>("backend = SmallRngBackend 1u8");
+        assert!(missing_semicolon.is_err());
+    }
+
     #[test]
     fn parse_paths() {
         let path = syn::parse_str::<FilePath>("\"//absolute/path\"")
@@ -0,0 +1,117 @@
+//! Tests for the derive `Authenticate` macro and the `authenticated` macro option.
+#![cfg(feature = "integrity")]
+
+// Required because the macros expands to call functions from "encrust" crate, which cannot be
+// imported into encrust_macros as this would introduce cyclic dependencies.
+extern crate encrust_core as encrust;
+
+use encrust_core::{Encrusted, IntegrityError};
+use encrust_macros::*;
+use zeroize::Zeroize;
+
+const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+#[derive(Authenticate, Clone, Debug, Encrustable, PartialEq, Zeroize)]
+struct Named {
+    byte: u8,
+    array: [u16; 7],
+    vec: Vec<i8>,
+    string: String,
+}
+
+#[derive(Authenticate, Clone, Debug, Encrustable, PartialEq, Zeroize)]
+enum NamedOrTuple {
+    Named { int: i32, string: String },
+    Tuple(u8, Vec<i8>),
+    _Unit,
+}
+
+fn gen_seed() -> u64 {
+    0x2357_bd11_1317_1d1f
+}
+
+#[test]
+fn derive_authenticate_struct() {
+    let named = Named {
+        byte: 31,
+        array: [6, 5, 4, 3, 2, 1, 0],
+        vec: vec![13, 37],
+        string: TEST_STRING.to_string(),
+    };
+    let original = named.clone();
+
+    let mut encrusted = Encrusted::new_authenticated(named, gen_seed());
+
+    {
+        let mut decrusted = encrusted
+            .try_decrust()
+            .expect("Data has not been tampered with");
+        assert_eq!(*decrusted, original);
+        decrusted.vec.push(1);
+    }
+
+    let decrusted = encrusted
+        .try_decrust()
+        .expect("Tag is updated after modification");
+    assert_eq!(decrusted.vec, vec![13, 37, 1]);
+}
+
+#[test]
+fn derive_authenticate_enum() {
+    let tuple = NamedOrTuple::Tuple(31, vec![13, 37]);
+    let original = tuple.clone();
+
+    let mut encrusted = Encrusted::new_authenticated(tuple, gen_seed());
+    assert_eq!(*encrusted.try_decrust().unwrap(), original);
+
+    let mut named = Encrusted::new_authenticated(
+        NamedOrTuple::Named {
+            int: 1337,
+            string: TEST_STRING.to_string(),
+        },
+        gen_seed(),
+    );
+    assert!(named.try_decrust().is_ok());
+}
+
+#[test]
+fn authenticated_macros() {
+    let mut s = encrust!(authenticated; "The quick brown fox jumps over the lazy dog😊");
+    assert_eq!(TEST_STRING, s.try_decrust().unwrap().as_str());
+
+    let mut n = encrust!(authenticated; -1isize);
+    assert_eq!(-1isize, *n.try_decrust().unwrap());
+
+    let mut array = encrust!(authenticated; [[1u8, 2u8], [3u8, 4u8]]);
+    assert_eq!([[1u8, 2u8], [3u8, 4u8]], *array.try_decrust().unwrap());
+
+    let mut numbers = encrust_vec![authenticated; 1u32, 2u32, 3u32, 4u32, 5u32, 6u32, 7u32, 8u32, 9u32, 10u32, 11u32, 12u32, 13u32, 14u32, 15u32, 16u32, 17u32];
+    assert_eq!(
+        (1u32..=17).collect::<Vec<_>>(),
+        *numbers.try_decrust().unwrap()
+    );
+
+    let mut sizes = encrust!(authenticated; [1usize, 2usize, 3usize, 4usize, 5usize, 6usize, 7usize, 8usize, 9usize]);
+    assert_eq!([1, 2, 3, 4, 5, 6, 7, 8, 9], *sizes.try_decrust().unwrap());
+
+    let mut vec = encrust_vec![authenticated; "a", "b", "c"];
+    assert_eq!(
+        vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        *vec.try_decrust().unwrap()
+    );
+
+    let mut file = encrust_file_bytes!(authenticated; "tests/authenticate.rs");
+    assert_eq!(
+        include_bytes!("authenticate.rs").as_slice(),
+        file.try_decrust().unwrap().as_slice()
+    );
+}
+
+#[test]
+fn unauthenticated_macros() {
+    let mut s = encrust!("The quick brown fox jumps over the lazy dog😊");
+    assert_eq!(
+        s.try_decrust().err(),
+        Some(IntegrityError::NotAuthenticated)
+    );
+}
@@ -1,8 +1,7 @@
 //! Tests for the derive `Encrustable` macro.
 
-use encrust_core::Encrustable;
+use encrust_core::{Backend, Encrustable, SmallRngBackend};
 use encrust_macros::*;
-use rand::{SeedableRng, rngs::SmallRng};
 use zeroize::Zeroize;
 
 const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
@@ -74,7 +73,7 @@ fn derive_named_ne() {
 
     let seed = gen_seed();
 
-    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
     // Safety: This is potentially unsafe, but used to test that encrusted data is not equal to
     // the underlying data.
@@ -123,7 +122,7 @@ fn derive_tuple_ne() {
 
     let seed = gen_seed();
 
-    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
     // Safety: This is potentially unsafe, but used to test that encrusted data is not equal to
     // the underlying data.
@@ -172,7 +171,7 @@ fn derive_enum_named_ne() {
 
     let seed = gen_seed();
 
-    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
     // Safety: This is potentially unsafe, but used to test that encrusted data is not equal to
     // the underlying data.
@@ -242,7 +241,7 @@ fn derive_enum_tuple_ne() {
 
     let seed = gen_seed();
 
-    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
     // Safety: This is potentially unsafe, but used to test that encrusted data is not equal to
     // the underlying data.
@@ -289,7 +288,7 @@ fn derive_with_generics_ne() {
 
     let seed = gen_seed();
 
-    let mut encrust_rng = SmallRng::seed_from_u64(seed);
+    let mut encrust_rng = SmallRngBackend::keystream(&seed);
 
     // Safety: This is potentially unsafe, but used to test that encrusted data is not equal to
     // the underlying data.
@@ -304,3 +303,70 @@ fn derive_with_generics_ne() {
     assert!(decrusted.1.ne(&original.1));
     assert!(decrusted.2.as_bytes().ne(original.2.as_bytes()));
 }
+
+#[test]
+fn derive_visit_allocations() {
+    let mut named = Named {
+        byte: 31,
+        int: 1337,
+        array: [6, 5, 4, 3, 2, 1, 0],
+        vec: vec![13, 37],
+        string: TEST_STRING.to_string(),
+    };
+    let expected = vec![
+        (named.vec.as_ptr().cast::<u8>(), named.vec.capacity()),
+        (named.string.as_ptr(), named.string.capacity()),
+    ];
+
+    let mut allocations = Vec::new();
+    named.visit_allocations(&mut |ptr, size| allocations.push((ptr, size)));
+    assert_eq!(expected, allocations);
+
+    let mut tuple = NamedOrTuple::Tuple(
+        31,
+        1337,
+        [6, 5, 4, 3, 2, 1, 0],
+        vec![13, 37],
+        TEST_STRING.to_string(),
+    );
+    let NamedOrTuple::Tuple(_, _, _, vec, string) = &tuple else {
+        unreachable!();
+    };
+    let expected = vec![
+        (vec.as_ptr().cast::<u8>(), vec.capacity()),
+        (string.as_ptr(), string.capacity()),
+    ];
+
+    let mut allocations = Vec::new();
+    tuple.visit_allocations(&mut |ptr, size| allocations.push((ptr, size)));
+    assert_eq!(expected, allocations);
+}
+
+#[derive(Encrustable, Zeroize)]
+struct WithConcealed {
+    number: u32,
+    concealed: encrust_core::ConcealedString,
+}
+
+#[test]
+fn derive_decrust_before_drop() {
+    const {
+        assert!(!<Named as Encrustable>::DECRUST_BEFORE_DROP);
+        assert!(!<NamedOrTuple as Encrustable>::DECRUST_BEFORE_DROP);
+        assert!(<WithConcealed as Encrustable>::DECRUST_BEFORE_DROP);
+    }
+
+    let mut encrusted = encrust_core::Encrusted::new(
+        WithConcealed {
+            number: 1337,
+            concealed: TEST_STRING.into(),
+        },
+        gen_seed(),
+    );
+
+    {
+        let decrusted = encrusted.decrust();
+        assert_eq!(1337, decrusted.number);
+        assert_eq!(TEST_STRING, &*decrusted.concealed);
+    }
+}
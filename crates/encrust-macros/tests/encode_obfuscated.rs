@@ -0,0 +1,81 @@
+//! Tests for the derive `EncodeObfuscated` macro.
+
+// Required because the macros expands to call functions from "encrust" crate, which cannot be
+// imported into encrust_macros as this would introduce cyclic dependencies.
+extern crate encrust_core as encrust;
+
+use encrust_core::Encrusted;
+use encrust_macros::*;
+use zeroize::Zeroize;
+
+const TEST_STRING: &str = "The quick brown fox jumps over the lazy dog😊";
+
+#[derive(Clone, Debug, EncodeObfuscated, Encrustable, PartialEq, Zeroize)]
+struct Named {
+    byte: u8,
+    array: [u16; 7],
+    vec: Vec<i8>,
+    string: String,
+}
+
+#[derive(Clone, Debug, EncodeObfuscated, Encrustable, PartialEq, Zeroize)]
+struct Tuple(Named, usize);
+
+#[derive(Clone, Debug, EncodeObfuscated, Encrustable, PartialEq, Zeroize)]
+enum NamedOrTuple {
+    Named { int: i32, string: String },
+    Tuple(u8, Vec<i8>),
+    Unit,
+}
+
+fn gen_seed() -> u64 {
+    0x2357_bd11_1317_1d1f
+}
+
+fn round_trip<T>(data: T) -> T
+where
+    T: Clone + encrust_core::Encrustable + encrust_core::EncodeObfuscated + Zeroize,
+{
+    let sealed = Encrusted::new(data, gen_seed()).to_sealed_bytes();
+    let mut encrusted = Encrusted::<T>::from_sealed_bytes(&sealed).unwrap();
+
+    encrusted.decrust().clone()
+}
+
+#[test]
+fn derive_encode_obfuscated_struct() {
+    let named = Named {
+        byte: 31,
+        array: [6, 5, 4, 3, 2, 1, 0],
+        vec: vec![13, 37],
+        string: TEST_STRING.to_string(),
+    };
+    let tuple = Tuple(named.clone(), 1337);
+
+    assert_eq!(named, round_trip(named.clone()));
+    assert_eq!(tuple, round_trip(tuple.clone()));
+}
+
+#[test]
+fn derive_encode_obfuscated_enum() {
+    for value in [
+        NamedOrTuple::Named {
+            int: -7,
+            string: TEST_STRING.to_string(),
+        },
+        NamedOrTuple::Tuple(42, vec![-1, 0, 1]),
+        NamedOrTuple::Unit,
+    ] {
+        assert_eq!(value, round_trip(value.clone()));
+    }
+}
+
+#[test]
+fn derive_invalid_variant() {
+    let mut sealed = Encrusted::new(NamedOrTuple::Unit, gen_seed()).to_sealed_bytes();
+
+    // The variant index is the first field of the payload, after 31 bytes of header, key and
+    // payload length.
+    sealed[31] = 3;
+    assert!(Encrusted::<NamedOrTuple>::from_sealed_bytes(&sealed).is_err());
+}
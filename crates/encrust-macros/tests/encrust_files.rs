@@ -18,8 +18,20 @@ fn encrust_file_string() {
 #[test]
 fn encrust_file_bytes() {
     let orig_file = include_bytes!("encrust_files.rs");
-    let mut encrust_file = encrust_macros::encrust_file_bytes!("tests/encrust_files.rs");
+    let mut encrust_file: encrust::EncrustedBytes =
+        encrust_macros::encrust_file_bytes!("tests/encrust_files.rs");
     let file = encrust_file.decrust();
 
     assert_eq!(orig_file.as_slice(), file.as_slice());
 }
+
+#[cfg(feature = "chacha20")]
+#[test]
+fn encrust_file_with_backend() {
+    let orig_file = include_str!("encrust_files.rs");
+    let mut encrust_file =
+        encrust_macros::encrust_file_string!(backend = ChaCha20Backend; "tests/encrust_files.rs");
+    let file = encrust_file.decrust();
+
+    assert_eq!(orig_file, file.as_str());
+}
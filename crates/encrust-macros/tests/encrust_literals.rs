@@ -84,6 +84,25 @@ fn encrust_arrays() {
         ],
         *decrusted
     );
+
+    let mut ua = encrust!([
+        [1u32, 2u32, 3u32, 4u32, 5u32, 6u32, 7u32, 8u32, 9u32],
+        [
+            10u32, 11u32, 12u32, 13u32, 14u32, 15u32, 16u32, 17u32, 18u32
+        ]
+    ]);
+    let decrusted = ua.decrust();
+    assert_eq!(
+        [
+            [1u32, 2, 3, 4, 5, 6, 7, 8, 9],
+            [10, 11, 12, 13, 14, 15, 16, 17, 18]
+        ],
+        *decrusted
+    );
+
+    let mut ia = encrust!([-1i128, 2i128, -3i128, 4i128, -5i128, 6i128]);
+    let decrusted = ia.decrust();
+    assert_eq!([-1i128, 2, -3, 4, -5, 6], *decrusted);
 }
 
 #[test]
@@ -108,7 +127,7 @@ fn encrust_vec() {
         1u8, 2u8, 3u8, 1u8, 2u8, 3u8, 1u8, 2u8, 3u8, 1u8, 2u8, 3u8, 1u8, 2u8, 3u8, 1u8, 2u8, 3u8,
         1u8, 2u8, 3u8, 1u8, 2u8, 3u8, 1u8, 2u8, 3u8
     ];
-    let decrusted = vec.decrust();
+    let decrusted: encrust::Decrusted<encrust::Bytes> = vec.decrust();
     assert_eq!(ORIG_ARRAY.to_vec(), *decrusted);
 
     let mut vec = encrust_vec![
@@ -128,3 +147,28 @@ fn encrust_vec() {
     let decrusted = vec.decrust();
     assert_eq!(orig_array3.to_vec(), *decrusted);
 }
+
+#[test]
+fn encrust_with_backends() {
+    let mut s =
+        encrust!(backend = SmallRngBackend; "The quick brown fox jumps over the lazy dog😊");
+    assert_eq!(TEST_STRING, s.decrust().as_str());
+
+    #[cfg(feature = "chacha20")]
+    {
+        let mut s =
+            encrust!(backend = ChaCha20Backend; "The quick brown fox jumps over the lazy dog😊");
+        assert_eq!(TEST_STRING, s.decrust().as_str());
+        let mut vec = encrust_vec![backend = ChaCha20Backend; 1u16, 2u16, 3u16];
+        assert_eq!(vec![1u16, 2u16, 3u16], *vec.decrust());
+    }
+
+    #[cfg(feature = "aes")]
+    {
+        let mut s =
+            encrust!(backend = AesCtrBackend; "The quick brown fox jumps over the lazy dog😊");
+        assert_eq!(TEST_STRING, s.decrust().as_str());
+        let mut vec = encrust_vec![backend = AesCtrBackend; 1u16, 2u16, 3u16];
+        assert_eq!(vec![1u16, 2u16, 3u16], *vec.decrust());
+    }
+}
@@ -72,6 +72,7 @@ pub extern "C" fn main() -> ! {
     let mut s = encrust!("Hi!");
     let mut n = encrust!([1u8, 2u8, 3u8]);
     let mut v = encrust_vec![3i8, 2i8, 1i8, 0i8];
+    let mut b = encrust_vec![3u8, 2u8, 1u8, 0u8];
     let hs = hashstring!("Hi!");
     let hsci = hashstring_ci!("hi!");
     let hb = hashbytes!([1, 2, 3]);
@@ -87,6 +88,10 @@ pub extern "C" fn main() -> ! {
         let decrusted = v.decrust();
         assert_eq!(&[3, 2, 1, 0], decrusted.as_slice());
     }
+    {
+        let decrusted = b.decrust();
+        assert_eq!(&[3, 2, 1, 0], decrusted.as_slice());
+    }
 
     assert!(hs == "Hi!");
     assert!(hsci == "Hi!");